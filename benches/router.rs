@@ -0,0 +1,62 @@
+//! Compare the route tree with a linear scan over one regex per route, as
+//! `App` used to route.
+//!
+//! Run with `cargo bench --bench router`.
+use std::time::Instant;
+
+use regex::Regex;
+use sincere::app::router::Router;
+use sincere::http::Method;
+
+const ROUTES: usize = 500;
+const ROUNDS: usize = 200;
+
+fn main() {
+    let mut router = Router::new();
+    let mut linear = Vec::new();
+
+    for i in 0..ROUTES {
+        let pattern = format!("/api/v1/resource{}/{{id:[0-9]+}}/items/{{item}}", i);
+        let regex = format!("^/api/v1/resource{}/([0-9]+)/items/([^/]*)$", i);
+
+        router.insert(Method::GET, &pattern, i);
+        linear.push((Method::GET, Regex::new(&regex).unwrap(), i));
+    }
+
+    let paths: Vec<String> = (0..ROUTES)
+        .map(|i| format!("/api/v1/resource{}/{}/items/abc", i, i * 7))
+        .collect();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for (i, path) in paths.iter().enumerate() {
+            let found = router.find(&Method::GET, path).unwrap();
+            assert_eq!(*found.value, i);
+        }
+    }
+    let tree = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for (i, path) in paths.iter().enumerate() {
+            let found = linear
+                .iter()
+                .filter(|(method, _, _)| *method == Method::GET)
+                .find_map(|(_, regex, value)| {
+                    regex.captures(path).map(|caps| {
+                        let params: Vec<String> = caps.iter().skip(1).map(|m| m.unwrap().as_str().to_owned()).collect();
+                        (value, params)
+                    })
+                })
+                .unwrap();
+            assert_eq!(*found.0, i);
+        }
+    }
+    let scan = start.elapsed();
+
+    let lookups = (ROUTES * ROUNDS) as u32;
+
+    println!("{} routes, {} lookups", ROUTES, lookups);
+    println!("route tree:  {:?} per lookup", tree / lookups);
+    println!("linear scan: {:?} per lookup", scan / lookups);
+}
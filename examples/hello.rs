@@ -1,13 +1,16 @@
 use sincere::App;
+use sincere::error::Result;
 
-fn main() {
+fn main() -> Result<()> {
     let mut app = App::new();
 
-    app.get("/", |context| {
+    app.get("/", |context| -> Result<()> {
 
         println!("{:?}", context.request);
-        context.response.from_text("Hello world!").unwrap();
+        context.response.from_text("Hello world!")?;
+
+        Ok(())
     });
 
-    app.run("0.0.0.0:10001").unwrap();
+    app.run("0.0.0.0:10001")
 }
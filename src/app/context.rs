@@ -1,37 +1,49 @@
 //! App context.
-use hyper;
-use hyper::body::Bytes;
+use std::mem;
+use std::sync::Arc;
+
+use hyper::Method;
 use http::request::Parts;
 
 use nson::Message;
 
 use super::App;
-use crate::http::Request;
+use crate::error::{Error, Result};
+use crate::http::{Payload, Request};
 use crate::http::Response;
+use crate::session::Session;
 
 /// App context.
-pub struct Context<'a> {
+pub struct Context {
     /// app container reference
-    pub app: &'a App,
+    pub app: Arc<App>,
     /// http request
     pub request: Request,
     /// http response
     pub response: Response,
     /// contexts key-value container
     pub contexts: Message,
+    pub(crate) session: Option<Session>,
+    /// index of the innermost group of the request
+    pub(crate) group: Option<usize>,
     stop: bool,
 }
 
-impl<'a> Context<'a> {
-    pub(crate) fn new(app: &App, parts: Parts, body: Bytes) -> Context {
-        let request = Request::from_hyper_request(parts, body);
-        let response = Response::empty(200);
+impl Context {
+    pub(crate) fn new(app: Arc<App>, parts: Parts, payload: Payload, max_body_size: Option<usize>) -> Context {
+        let mut request = Request::from_hyper_request(parts, payload, max_body_size);
+        let mut response = Response::empty(200);
+
+        request.key = app.secret_key.clone();
+        response.key = app.secret_key.clone();
 
         Context {
             app: app,
             request: request,
             response: response,
             contexts: Message::new(),
+            session: None,
+            group: None,
             stop: false,
         }
     }
@@ -73,11 +85,40 @@ impl<'a> Context<'a> {
         self.stop = true;
     }
 
+    /// The session of the request, loaded by the `session::Sessions`
+    /// middleware. Fails if it is not used.
+    pub fn session(&mut self) -> Result<&mut Session> {
+        self.session
+            .as_mut()
+            .ok_or_else(|| Error::Error("The session middleware is not used".to_owned()))
+    }
+
+    /// Stop the handle to continue, and hand the error to the app, which
+    /// writes it into the response.
+    pub(crate) fn fail(&mut self, err: Error) {
+        self.stop();
+
+        let app = self.app.clone();
+        app.handle_error(self, err);
+    }
+
+    /// Take the context out, leaving an empty one with the same app.
+    pub(crate) fn take(&mut self) -> Context {
+        let (parts, _) = http::Request::new(()).into_parts();
+        let empty = Context::new(self.app.clone(), parts, Payload::Buffered(Default::default()), None);
+
+        mem::replace(self, empty)
+    }
+
     pub(crate) fn next(&self) -> bool {
         !self.stop
     }
 
-    pub(crate) fn finish(self) -> hyper::Response<hyper::Body> {
-        self.response.raw_response()
+    pub(crate) fn finish(mut self) -> Response {
+        if self.request.method() == Method::HEAD {
+            self.response.strip_body();
+        }
+
+        self.response
     }
 }
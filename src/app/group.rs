@@ -1,10 +1,16 @@
 /// Route group
 use std::collections::HashMap;
+use std::future::Future;
+use std::mem;
+use std::path::Path;
 
 use super::context::Context;
-use super::middleware::Middleware;
+use super::{async_handler, handler, AsyncOutput, ErrorHandle};
+use super::middleware::{Hook, Hooks, Middleware};
 use super::route::Route;
-use crate::http::Method;
+use super::static_dir::StaticDir;
+use crate::error::Error;
+use crate::http::{IntoResponse, Method};
 
 // use hyper::Method;
 
@@ -52,8 +58,16 @@ use crate::http::Method;
 pub struct Group {
     pub routes: HashMap<Method, Vec<Route>>,
     prefix: String,
-    pub before: Vec<Middleware>,
-    pub after: Vec<Middleware>,
+    groups: Vec<Group>,
+    pub(crate) parent: Option<usize>,
+    middleware: Vec<Box<dyn Middleware>>,
+    pub begin: Vec<Hook>,
+    pub before: Vec<Hook>,
+    pub after: Vec<Hook>,
+    pub finish: Vec<Hook>,
+    pub(crate) not_found: Option<Hook>,
+    pub(crate) on_error: Option<Box<ErrorHandle>>,
+    max_body_size: Option<usize>,
 }
 
 impl Group {
@@ -71,11 +85,214 @@ impl Group {
         Group {
             routes: HashMap::new(),
             prefix: prefix.to_owned(),
+            groups: Vec::new(),
+            parent: None,
+            middleware: Vec::new(),
+            begin: Vec::new(),
             before: Vec::new(),
             after: Vec::new(),
+            finish: Vec::new(),
+            not_found: None,
+            on_error: None,
+            max_body_size: None,
         }
     }
 
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Mount a group inside this one, under the concatenation of both
+    /// prefixes.
+    ///
+    /// The middleware and hooks of this group run around the ones of the
+    /// nested group, and its body limit, not-found and error handles apply
+    /// unless the nested group has its own.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.mount("/api", |api| {
+    ///     api.mount("/v1", |v1| {
+    ///         v1.get("/users", |_| "Users!");
+    ///
+    ///         v1.mount("/admin", |admin| {
+    ///             admin.before(|context| {
+    ///                 if context.request.header("Token").is_none() {
+    ///                     context.response.status_code(401);
+    ///                     context.stop();
+    ///                 }
+    ///             });
+    ///
+    ///             admin.get("/stats", |_| "Stats!");
+    ///         });
+    ///     });
+    /// });
+    ///
+    /// let client = app.test_client();
+    ///
+    /// assert_eq!(client.get("/api/v1/users").send().get_body(), b"Users!");
+    /// assert_eq!(client.get("/api/v1/admin/stats").send().get_status_code(), 401);
+    /// assert_eq!(client.get("/api/v1/admin/stats").header("Token", "t").send().get_body(), b"Stats!");
+    /// ```
+    pub fn mount<F>(&mut self, prefix: &str, func: F) -> &mut Group
+        where F: Fn(&mut Group)
+    {
+        let mut group = Group::new(prefix);
+
+        func(&mut group);
+
+        self.mount_group(group)
+    }
+
+    /// Mount a group built on its own inside this one, see `Group::mount`.
+    pub fn mount_group(&mut self, mut group: Group) -> &mut Group {
+        group.prepend(&self.prefix);
+
+        self.groups.push(group);
+        self
+    }
+
+    /// Add `not-found handle` to group.
+    ///
+    /// It answers the requests under the prefix of the group that no route
+    /// matches, instead of the one of the app or an outer group.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.mount("/api", |group| {
+    ///     group.not_found(|context| {
+    ///         context.response.status_code(404).from_json(&serde_json::json!({"error": "Not Found"})).unwrap();
+    ///     });
+    /// });
+    ///
+    /// let client = app.test_client();
+    ///
+    /// assert_eq!(client.get("/api/missing").send().get_body(), br#"{"error":"Not Found"}"#);
+    /// assert_eq!(client.get("/missing").send().get_body(), b"Not Found");
+    /// ```
+    pub fn not_found<H, R>(&mut self, handle: H) -> &mut Group
+        where H: Fn(&mut Context) -> R + Send + Sync + 'static,
+              R: IntoResponse
+    {
+        self.not_found = Some(Hook::new(handle));
+        self
+    }
+
+    /// Add `error handle` to group.
+    ///
+    /// It handles the errors of the requests under the prefix of the group,
+    /// instead of the one of the app or an outer group, see `App::on_error`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    /// use sincere::error::Error;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.mount("/api", |group| {
+    ///     group.get("/", |_| -> Result<(), Error> {
+    ///         Err(Error::Error("Oops".to_owned()))
+    ///     });
+    ///
+    ///     group.on_error(|context, err| {
+    ///         let body = serde_json::json!({"error": err.to_string()});
+    ///         context.response.status_code(err.status_code()).from_json(&body).ok();
+    ///     });
+    /// });
+    ///
+    /// let response = app.test_client().get("/api/").send();
+    ///
+    /// assert_eq!(response.get_status_code(), 500);
+    /// assert_eq!(response.get_body(), br#"{"error":"Oops"}"#);
+    /// ```
+    pub fn on_error<H>(&mut self, handle: H) -> &mut Group
+        where H: Fn(&mut Context, Error) + Send + Sync + 'static
+    {
+        self.on_error = Some(Box::new(handle));
+        self
+    }
+
+    /// Whether a path is under the prefix of the group.
+    pub(crate) fn contains(&self, path: &str) -> bool {
+        let prefix = self.prefix.trim_end_matches('/');
+
+        match path.strip_prefix(prefix) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+
+    /// Move the group, and the groups nested in it, under a prefix.
+    fn prepend(&mut self, prefix: &str) {
+        self.prefix = prefix.to_owned() + &self.prefix;
+
+        for routes in self.routes.values_mut() {
+            for route in routes.iter_mut() {
+                route.prepend(prefix);
+            }
+        }
+
+        for group in self.groups.iter_mut() {
+            group.prepend(prefix);
+        }
+    }
+
+    /// Take out the groups nested in this one.
+    pub(crate) fn take_groups(&mut self) -> Vec<Group> {
+        mem::take(&mut self.groups)
+    }
+
+    /// Limit the request body of the routes in this group to `size` bytes,
+    /// overriding the limit of the outer groups and the app.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::app::Group;
+    ///
+    /// let mut group = Group::new("/upload");
+    ///
+    /// group.max_body_size(64 * 1024 * 1024);
+    /// ```
+    pub fn max_body_size(&mut self, size: usize) -> &mut Group {
+        self.max_body_size = Some(size);
+        self
+    }
+
+    pub fn get_max_body_size(&self) -> Option<usize> {
+        self.max_body_size
+    }
+
+    /// Serve the files of a directory under `prefix`, see `App::static_dir`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::app::Group;
+    ///
+    /// let mut group = Group::new("/app");
+    ///
+    /// group.static_dir("/assets", "./public");
+    /// ```
+    pub fn static_dir<P: AsRef<Path>>(&mut self, prefix: &str, dir: P) -> &mut Route {
+        let dir = StaticDir::new(dir.as_ref().to_path_buf());
+        let pattern = format!("{}/{{path:.*}}", prefix.trim_end_matches('/'));
+
+        self.get(&pattern, move |context| dir.serve(context))
+    }
+
     /// Add route handle to group.
     ///
     /// # Examples
@@ -90,17 +307,18 @@ impl Group {
     ///     context.response.from_text("Get method!").unwrap();
     /// });
     /// ```
-    pub fn add<H>(&mut self, method: Method, pattern: &str, handle: H) -> &mut Route
+    pub fn add<H, R>(&mut self, method: Method, pattern: &str, handle: H) -> &mut Route
     where
-        H: Fn(&mut Context) + Send + Sync + 'static,
+        H: Fn(&mut Context) -> R + Send + Sync + 'static,
+        R: IntoResponse,
     {
         let route = Route::new(
             method.clone(),
             self.prefix.clone() + pattern,
-            Box::new(handle),
+            handler(handle),
         );
 
-        let routes = self.routes.entry(method).or_insert(Vec::new());
+        let routes = self.routes.entry(method).or_default();
         routes.push(route);
         routes.last_mut().unwrap()
     }
@@ -258,6 +476,115 @@ impl Group {
         connect
     );
 
+    /// Add asynchronous route handle to group.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::app::Group;
+    /// use sincere::http::Method;
+    ///
+    /// let mut group = Group::new("/app");
+    ///
+    /// group.add_async(Method::GET, "/", |mut context| async move {
+    ///     context.response.from_text("Get method!").unwrap();
+    ///     context
+    /// });
+    /// ```
+    pub fn add_async<H, F>(&mut self, method: Method, pattern: &str, handle: H) -> &mut Route
+    where
+        H: Fn(Context) -> F + Send + Sync + 'static,
+        F: Future + Send + 'static,
+        F::Output: AsyncOutput,
+    {
+        let route = Route::new_async(
+            method.clone(),
+            self.prefix.clone() + pattern,
+            async_handler(handle),
+        );
+
+        let routes = self.routes.entry(method).or_default();
+        routes.push(route);
+        routes.last_mut().unwrap()
+    }
+
+    route_async!(
+        /// Add asynchronous route handle to group with GET method.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use sincere::app::Group;
+        ///
+        /// let mut group = Group::new("/group");
+        ///
+        /// group.get_async("/", |mut context| async move {
+        ///     context.response.from_text("Get method!").unwrap();
+        ///     context
+        /// });
+        /// ```
+        get_async, GET
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to group with PUT method.
+        put_async, PUT
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to group with POST method.
+        post_async, POST
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to group with HEAD method.
+        head_async, HEAD
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to group with PATCH method.
+        patch_async, PATCH
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to group with TRACE method.
+        trace_async, TRACE
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to group with DELETE method.
+        delete_async, DELETE
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to group with OPTIONS method.
+        options_async, OPTIONS
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to group with CONNECT method.
+        connect_async, CONNECT
+    );
+
+    middleware!(
+        /// Add `begin handle` to group, run before its middleware for its
+        /// routes and those of its nested groups, even once the context is
+        /// stopped.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use sincere::app::Group;
+        ///
+        /// let mut group = Group::new("/app");
+        ///
+        /// group.begin(|context| {
+        ///     context.response.from_text("begin!").unwrap();
+        /// });
+        /// ```
+        begin
+    );
+
     middleware!(
         /// Add `before handle` to group.
         ///
@@ -291,4 +618,67 @@ impl Group {
         /// ```
         after
     );
+
+    middleware!(
+        /// Add `finish handle` to group, run after its middleware for its
+        /// routes and those of its nested groups, even once the context is
+        /// stopped.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use sincere::app::Group;
+        ///
+        /// let mut group = Group::new("/app");
+        ///
+        /// group.finish(|context| {
+        ///     context.response.from_text("finish!").unwrap();
+        /// });
+        /// ```
+        finish
+    );
+
+    /// Wrap the routes of the group in a middleware, run after the app's.
+    /// The asynchronous handles it wraps run on the blocking pool, see
+    /// `App::wrap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::app::Group;
+    /// use sincere::app::middleware::from_fn;
+    ///
+    /// let mut group = Group::new("/app");
+    ///
+    /// group.wrap(from_fn(|context, next| {
+    ///     next.run(context);
+    ///     context.response.header(("Cache-Control", "no-store"))?;
+    ///
+    ///     Ok(())
+    /// }));
+    /// ```
+    pub fn wrap<M: Middleware + 'static>(&mut self, middleware: M) -> &mut Group {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    pub(crate) fn get_middleware(&self) -> &[Box<dyn Middleware>] {
+        &self.middleware
+    }
+
+    pub(crate) fn outer_hooks(&self) -> Hooks<'_> {
+        Hooks {
+            before: &self.begin,
+            after: &self.finish,
+            always: true,
+        }
+    }
+
+    pub(crate) fn hooks(&self) -> Hooks<'_> {
+        Hooks {
+            before: &self.before,
+            after: &self.after,
+            always: false,
+        }
+    }
 }
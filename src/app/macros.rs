@@ -2,8 +2,9 @@
 macro_rules! route {
     ($(#[$meta:meta])* $func_name:ident) => (
         $(#[$meta])*
-        pub fn $func_name<H>(&mut self, pattern: &str, handle: H) -> &mut Route
-            where H: Fn(&mut Context) + Send + Sync + 'static
+        pub fn $func_name<H, R>(&mut self, pattern: &str, handle: H) -> &mut Route
+            where H: Fn(&mut Context) -> R + Send + Sync + 'static,
+                  R: $crate::http::IntoResponse
         {
             self.add(stringify!($func_name).to_uppercase().parse().unwrap(), pattern, handle)
         }
@@ -14,14 +15,27 @@ macro_rules! route {
 macro_rules! middleware {
     ($(#[$meta:meta])* $func_name:ident) => (
         $(#[$meta])*
-        pub fn $func_name<H>(&mut self, handle: H) -> &mut Self
-            where H: Fn(&mut Context) + Send + Sync + 'static
+        pub fn $func_name<H, R>(&mut self, handle: H) -> &mut Self
+            where H: Fn(&mut Context) -> R + Send + Sync + 'static,
+                  R: $crate::http::IntoResponse
         {
-            self.$func_name.push(Middleware {
-                inner: Box::new(handle),
-            });
+            self.$func_name.push($crate::app::middleware::Hook::new(handle));
 
             self
         }
     )
 }
+
+#[macro_export]
+macro_rules! route_async {
+    ($(#[$meta:meta])* $func_name:ident, $method:ident) => (
+        $(#[$meta])*
+        pub fn $func_name<H, F>(&mut self, pattern: &str, handle: H) -> &mut Route
+            where H: Fn(Context) -> F + Send + Sync + 'static,
+                  F: ::std::future::Future + Send + 'static,
+                  F::Output: $crate::app::AsyncOutput
+        {
+            self.add_async($crate::http::Method::$method, pattern, handle)
+        }
+    )
+}
@@ -1,18 +1,200 @@
+//! Middleware.
+//!
+//! A [`Middleware`](trait.Middleware.html) wraps everything after it, down
+//! to the route handle, and decides when, and whether, to run it with
+//! [`Next`](struct.Next.html). It can be set on the app, a group or a
+//! route, the app's run first. It runs on the blocking pool, and so do
+//! the asynchronous handles it wraps, which are awaited in place.
+//!
+//! The `begin`, `before`, `after` and `finish` handles are hooks run by
+//! adapters in that chain: the app's `begin` and `finish` hooks wrap the
+//! whole of it and a group's wrap its middleware, even once the context is
+//! stopped, while the `before` and `after` hooks of the app, group and
+//! route each wrap what follows their middleware.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Instant;
+//!
+//! use sincere::App;
+//! use sincere::app::middleware::from_fn;
+//!
+//! let mut app = App::new();
+//!
+//! app.wrap(from_fn(|context, next| {
+//!     let start = Instant::now();
+//!
+//!     next.run(context);
+//!
+//!     let elapsed = format!("{}us", start.elapsed().as_micros());
+//!     context.response.header(("X-Response-Time", elapsed))?;
+//!
+//!     Ok(())
+//! }));
+//!
+//! app.get("/", |_| "Hello world!");
+//!
+//! let response = app.test_client().get("/").send();
+//!
+//! assert!(response.get_header("X-Response-Time").is_some());
+//! ```
 use super::context::Context;
-use super::Handle;
+use super::{handler, Handle};
+use crate::error::Result;
+use crate::http::IntoResponse;
 
-pub struct Middleware {
+/// Code run around the rest of the chain.
+pub trait Middleware: Send + Sync {
+    /// Handle the context, running the rest of the chain with `next` or
+    /// answering without it. An error stops the context and is handed to
+    /// the app.
+    fn call(&self, context: &mut Context, next: Next<'_>) -> Result<()>;
+}
+
+/// A middleware from a closure.
+///
+/// # Examples
+///
+/// ```
+/// use sincere::App;
+/// use sincere::app::middleware::from_fn;
+///
+/// let mut app = App::new();
+///
+/// app.get("/admin", |_| "Welcome!").wrap(from_fn(|context, next| {
+///     if context.request.header("Token").as_deref() == Some("secret") {
+///         next.run(context);
+///     } else {
+///         context.response.status_code(401);
+///     }
+///
+///     Ok(())
+/// }));
+///
+/// let client = app.test_client();
+///
+/// assert_eq!(client.get("/admin").send().get_status_code(), 401);
+/// assert_eq!(client.get("/admin").header("Token", "secret").send().get_body(), b"Welcome!");
+/// ```
+pub fn from_fn<F>(func: F) -> FromFn<F>
+    where F: Fn(&mut Context, Next<'_>) -> Result<()> + Send + Sync + 'static
+{
+    FromFn(func)
+}
+
+/// A middleware made by `from_fn`.
+pub struct FromFn<F>(F);
+
+impl<F> Middleware for FromFn<F>
+    where F: Fn(&mut Context, Next<'_>) -> Result<()> + Send + Sync + 'static
+{
+    fn call(&self, context: &mut Context, next: Next<'_>) -> Result<()> {
+        (self.0)(context, next)
+    }
+}
+
+/// The rest of the chain, down to the route handle.
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    chain: &'a [&'a dyn Middleware],
+    endpoint: &'a (dyn Fn(&mut Context) + Sync),
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(chain: &'a [&'a dyn Middleware], endpoint: &'a (dyn Fn(&mut Context) + Sync)) -> Next<'a> {
+        Next { chain, endpoint }
+    }
+
+    /// Run the rest of the chain, nothing is run once the context is
+    /// stopped.
+    pub fn run(&self, context: &mut Context) {
+        if context.next() {
+            self.call(context);
+        }
+    }
+
+    /// Run the rest of the chain, even if the context is stopped.
+    pub(crate) fn call(&self, context: &mut Context) {
+        match self.chain.split_first() {
+            Some((middleware, chain)) => {
+                let next = Next { chain, endpoint: self.endpoint };
+
+                if let Err(err) = middleware.call(context, next) {
+                    context.fail(err);
+                }
+            }
+            None => (self.endpoint)(context),
+        }
+    }
+}
+
+/// A `begin`, `before`, `after`, `finish` or fallback handle.
+pub struct Hook {
     pub inner: Box<Handle>,
 }
 
-impl Middleware {
+impl Hook {
+    pub fn new<H, R>(handle: H) -> Hook
+        where H: Fn(&mut Context) -> R + Send + Sync + 'static,
+              R: IntoResponse
+    {
+        Hook {
+            inner: handler(handle),
+        }
+    }
+
     pub fn execute(&self, context: &mut Context) {
         if context.next() {
-            (self.inner)(context);
+            self.execute_always(context);
         }
     }
 
+    /// Execute even if the context was stopped, an error stops it and is
+    /// handed to the app.
     pub fn execute_always(&self, context: &mut Context) {
-        (self.inner)(context);
+        if let Err(err) = (self.inner)(context) {
+            context.fail(err);
+        }
+    }
+}
+
+/// The adapter running hooks around the rest of the chain.
+///
+/// With `always`, the hooks run even once the context is stopped, as the
+/// `begin` and `finish` hooks do.
+pub(crate) struct Hooks<'a> {
+    pub before: &'a [Hook],
+    pub after: &'a [Hook],
+    pub always: bool,
+}
+
+impl<'a> Hooks<'a> {
+    pub fn run_before(&self, context: &mut Context) {
+        run(self.before, context, self.always);
+    }
+
+    pub fn run_after(&self, context: &mut Context) {
+        run(self.after, context, self.always);
+    }
+}
+
+impl<'a> Middleware for Hooks<'a> {
+    fn call(&self, context: &mut Context, next: Next<'_>) -> Result<()> {
+        self.run_before(context);
+        next.run(context);
+        self.run_after(context);
+
+        Ok(())
+    }
+}
+
+fn run(hooks: &[Hook], context: &mut Context, always: bool) {
+    for hook in hooks {
+        if always {
+            hook.execute_always(context);
+        } else {
+            hook.execute(context);
+        }
     }
 }
@@ -1,27 +1,93 @@
 //! App container.
-use hyper::{self, Server, Request, Response, Body, Method};
-use hyper::service::{service_fn, make_service_fn};
-use hyper::body::Bytes;
+use std::any::Any;
+use std::cmp::Reverse;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use hyper::{Response, Body, Method};
+use hyper::header::{self, HeaderValue};
 use http::request::Parts;
-use tokio::runtime::Runtime;
 use tokio::task;
 
-use queen_log::color::Print;
-
 pub use self::route::Route;
 pub use self::group::Group;
-use self::middleware::Middleware;
+pub use self::server::{Server, ServerHandle, shutdown_signal};
+use self::middleware::{Hook, Hooks, Middleware, Next};
 use self::context::Context;
+use self::router::Router;
 use crate::error::Result;
+use crate::error::Error;
+use crate::http::{BodyStream, IntoResponse, Payload};
+use crate::http::cookie::Key;
+use crate::testing::TestClient;
+use crate::util::executor::catch_unwind;
 
 #[macro_use]
 mod macros;
 mod route;
 mod group;
+mod server;
+mod static_dir;
 pub mod middleware;
+pub mod router;
 pub mod context;
 
-pub type Handle = dyn Fn(&mut Context) + Send + Sync + 'static;
+pub type Handle = dyn Fn(&mut Context) -> Result<()> + Send + Sync + 'static;
+
+pub type AsyncHandle = dyn Fn(Context) -> Pin<Box<dyn Future<Output = Context> + Send>> + Send + Sync + 'static;
+
+pub type ErrorHandle = dyn Fn(&mut Context, Error) + Send + Sync + 'static;
+
+pub type PanicHandle = dyn Fn(&mut crate::http::Response, &Panic) + Send + Sync + 'static;
+
+/// Box a handle, writing what it returns into the response.
+pub(crate) fn handler<H, R>(handle: H) -> Box<Handle>
+    where H: Fn(&mut Context) -> R + Send + Sync + 'static,
+          R: IntoResponse
+{
+    Box::new(move |context| handle(context).into_response(&mut context.response))
+}
+
+/// What an asynchronous handle resolves to: the context, or the context and
+/// a value written into the response, such as a `Result` whose error is
+/// handed to the app.
+pub trait AsyncOutput: Send + 'static {
+    fn into_context(self) -> Context;
+}
+
+impl AsyncOutput for Context {
+    fn into_context(self) -> Context {
+        self
+    }
+}
+
+impl<R: IntoResponse + Send + 'static> AsyncOutput for (Context, R) {
+    fn into_context(self) -> Context {
+        let (mut context, value) = self;
+
+        if let Err(err) = value.into_response(&mut context.response) {
+            context.fail(err);
+        }
+
+        context
+    }
+}
+
+/// Box an asynchronous handle, writing what it returns into the response.
+pub(crate) fn async_handler<H, F>(handle: H) -> Box<AsyncHandle>
+    where H: Fn(Context) -> F + Send + Sync + 'static,
+          F: Future + Send + 'static,
+          F::Output: AsyncOutput
+{
+    Box::new(move |context| {
+        let future = handle(context);
+        Box::pin(async move { future.await.into_context() })
+    })
+}
 
 /// App container.
 ///
@@ -42,11 +108,18 @@ pub type Handle = dyn Fn(&mut Context) + Send + Sync + 'static;
 #[derive(Default)]
 pub struct App {
     groups: Vec<Group>,
-    begin: Vec<Middleware>,
-    before: Vec<Middleware>,
-    after: Vec<Middleware>,
-    finish: Vec<Middleware>,
-    not_found: Option<Middleware>
+    middleware: Vec<Box<dyn Middleware>>,
+    begin: Vec<Hook>,
+    before: Vec<Hook>,
+    after: Vec<Hook>,
+    finish: Vec<Hook>,
+    not_found: Option<Hook>,
+    method_not_allowed: Option<Hook>,
+    on_error: Option<Box<ErrorHandle>>,
+    on_panic: Option<Box<PanicHandle>>,
+    pub(crate) secret_key: Option<Key>,
+    max_body_size: Option<usize>,
+    router: Router<RouteId>
 }
 
 impl App {
@@ -63,11 +136,18 @@ impl App {
     pub fn new() -> App {
         App {
             groups: vec![Group::new("")],
+            middleware: Vec::new(),
             begin: Vec::new(),
             before: Vec::new(),
             after: Vec::new(),
             finish: Vec::new(),
-            not_found: None
+            not_found: None,
+            method_not_allowed: None,
+            on_error: None,
+            on_panic: None,
+            secret_key: None,
+            max_body_size: None,
+            router: Router::new()
         }
     }
 
@@ -85,8 +165,9 @@ impl App {
     ///     context.response.from_text("Get method!").unwrap();
     /// });
     /// ```
-    pub fn add<H>(&mut self, method: Method, pattern: &str, handle: H) -> &mut Route
-        where H: Fn(&mut Context) + Send + Sync + 'static
+    pub fn add<H, R>(&mut self, method: Method, pattern: &str, handle: H) -> &mut Route
+        where H: Fn(&mut Context) -> R + Send + Sync + 'static,
+              R: IntoResponse
     {
         self.groups.get_mut(0).unwrap().add(method, pattern, handle)
     }
@@ -254,6 +335,121 @@ impl App {
         connect
     );
 
+    /// Add asynchronous route handle to app.
+    ///
+    /// The handle takes the context by value and gives it back once the
+    /// response is ready, alone or with a value written into the response as
+    /// a sync handle returns it, see `AsyncOutput`. It is awaited on the
+    /// runtime instead of occupying a thread of the blocking pool, so
+    /// I/O-bound handles should prefer it, unless a middleware wraps it, see
+    /// `App::wrap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    /// use sincere::error::Result;
+    /// use sincere::http::Method;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.add_async(Method::GET, "/", |mut context| async move {
+    ///     context.response.from_text("Get method!").unwrap();
+    ///     context
+    /// });
+    ///
+    /// app.add_async(Method::POST, "/", |mut context| async move {
+    ///     let result: Result<String> = async {
+    ///         let name: String = context.request.bind_json()?;
+    ///         Ok(format!("Hello {}!", name))
+    ///     }.await;
+    ///
+    ///     (context, result)
+    /// });
+    ///
+    /// let response = app.test_client().post("/").body("\"sincere\"").send();
+    ///
+    /// assert_eq!(response.get_body(), b"Hello sincere!");
+    /// ```
+    pub fn add_async<H, F>(&mut self, method: Method, pattern: &str, handle: H) -> &mut Route
+        where H: Fn(Context) -> F + Send + Sync + 'static,
+              F: Future + Send + 'static,
+              F::Output: AsyncOutput
+    {
+        self.groups.get_mut(0).unwrap().add_async(method, pattern, handle)
+    }
+
+    route_async!(
+        /// Add asynchronous route handle to app with GET method.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use sincere::App;
+        ///
+        /// let mut app = App::new();
+        ///
+        /// app.get_async("/", |mut context| async move {
+        ///     context.response.from_text("Get method!").unwrap();
+        ///     context
+        /// });
+        /// ```
+        get_async, GET
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to app with PUT method.
+        put_async, PUT
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to app with POST method.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use sincere::App;
+        ///
+        /// let mut app = App::new();
+        ///
+        /// app.post_async("/", |mut context| async move {
+        ///     context.response.from_text("Post method!").unwrap();
+        ///     context
+        /// });
+        /// ```
+        post_async, POST
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to app with HEAD method.
+        head_async, HEAD
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to app with PATCH method.
+        patch_async, PATCH
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to app with TRACE method.
+        trace_async, TRACE
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to app with DELETE method.
+        delete_async, DELETE
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to app with OPTIONS method.
+        options_async, OPTIONS
+    );
+
+    route_async!(
+        /// Add asynchronous route handle to app with CONNECT method.
+        connect_async, CONNECT
+    );
+
     /// Mount router group to app.
     ///
     /// # Examples
@@ -278,12 +474,11 @@ impl App {
     pub fn mount<F>(&mut self, prefix: &str, func: F) -> &mut App
         where F: Fn(&mut Group)
     {
-        let mut group = Group::new(prefix); 
+        let mut group = Group::new(prefix);
 
         func(&mut group);
-        
-        self.groups.push(group);
-        self
+
+        self.mount_group(group)
     }
 
     /// Mount router group to app.
@@ -309,10 +504,49 @@ impl App {
     /// app.mount_group(group);
     ///
     pub fn mount_group(&mut self, group: Group) -> &mut App {
-        self.groups.push(group);
+        self.push_group(group, None);
         self
     }
 
+    /// Add a group and the groups nested in it, which keep the index of
+    /// their parent.
+    fn push_group(&mut self, mut group: Group, parent: Option<usize>) {
+        let nested = group.take_groups();
+        let index = self.groups.len();
+
+        group.parent = parent;
+        self.groups.push(group);
+
+        for group in nested {
+            self.push_group(group, Some(index));
+        }
+    }
+
+    /// A group and the groups it is nested in, the outermost first.
+    fn lineage(&self, index: usize) -> Vec<&Group> {
+        let mut lineage = Vec::new();
+        let mut index = Some(index);
+
+        while let Some(i) = index {
+            lineage.push(&self.groups[i]);
+            index = self.groups[i].parent;
+        }
+
+        lineage.reverse();
+        lineage
+    }
+
+    /// The innermost group whose prefix the path is under, the app's own
+    /// routes aside. Among groups with the same prefix, the most nested one
+    /// wins, then the first mounted.
+    fn group_of(&self, path: &str) -> Option<usize> {
+        (1..self.groups.len())
+            .filter(|&index| self.groups[index].contains(path))
+            .max_by_key(|&index| {
+                (self.groups[index].prefix().len(), self.lineage(index).len(), Reverse(index))
+            })
+    }
+
     middleware!(
         /// Add `begin handle` to app.
         ///
@@ -381,6 +615,42 @@ impl App {
         finish
     );
 
+    /// Wrap every request in a middleware, inside the `begin` and `finish`
+    /// hooks and around the `before` and `after` ones. Requests no route
+    /// matched are wrapped too. See the `middleware` module.
+    ///
+    /// Middleware runs on the blocking pool, so the asynchronous handles it
+    /// wraps are awaited there too, each holding a thread, instead of on
+    /// the runtime. A warning is logged for those routes when the app is
+    /// started, hooks don't have this cost.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    /// use sincere::app::middleware::from_fn;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.wrap(from_fn(|context, next| {
+    ///     next.run(context);
+    ///
+    ///     if context.response.get_status_code() == 404 {
+    ///         context.response.from_text("Nothing here")?;
+    ///     }
+    ///
+    ///     Ok(())
+    /// }));
+    ///
+    /// let response = app.test_client().get("/missing").send();
+    ///
+    /// assert_eq!(response.get_body(), b"Nothing here");
+    /// ```
+    pub fn wrap<M: Middleware + 'static>(&mut self, middleware: M) -> &mut App {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
     /// Use middleware
     ///
     /// # Example
@@ -422,183 +692,1343 @@ impl App {
     ///     context.response.status_code(404).from_text("Not Found!").unwrap();
     /// });
     /// ```
-    pub fn not_found<H>(&mut self, handle: H)
-        where H: Fn(&mut Context) + Send + Sync + 'static
+    pub fn not_found<H, R>(&mut self, handle: H)
+        where H: Fn(&mut Context) -> R + Send + Sync + 'static,
+              R: IntoResponse
     {
-        self.not_found = Some(Middleware {
-            inner: Box::new(handle),
-        });
+        self.not_found = Some(Hook::new(handle));
     }
 
-    /// handle
-    fn handle(&self, parts: Parts, body: Bytes) -> Response<Body> {
-
-        let mut context = Context::new(self, parts, body);
-
-        let mut route_found = false;
-
-        for begin in self.begin.iter() {
-            begin.execute_always(&mut context);
-        }
-
-        if context.next() {
-            let path = {
-                let path = context.request.uri().path();
-                if path != "/" {
-                    path.trim_end_matches('/').to_owned()
-                } else {
-                    path.to_owned()
-                }
-            };
-
-            'outer: for group in self.groups.iter() {
-
-                if let Some(routes) = group.routes.get(context.request.method()) {
-
-                    for route in routes.iter() {
-                        if let Some(ref regex) = route.regex {
-                            let caps = regex.captures(&path);
-
-                            if let Some(caps) = caps {
-                                route_found = true;
-
-                                let matches = route.path();
+    /// Add `error handle` to app.
+    ///
+    /// It is called with the error a handle or middleware returned, once the
+    /// context is stopped. Without it, the response is set by
+    /// `Response::from_error`, which the hook may fall back to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::{App, Error};
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/", |_| -> Result<(), Error> {
+    ///     Err(Error::Error("Oops".to_owned()))
+    /// });
+    ///
+    /// app.get("/user/{id}", |context| -> Result<(), Error> {
+    ///     let id: u32 = context.request.param("id").unwrap_or_default().parse()?;
+    ///     context.response.from_text(id.to_string())?;
+    ///     Ok(())
+    /// });
+    ///
+    /// app.on_error(|context, err| {
+    ///     match err {
+    ///         Error::ParseIntError(_) => {
+    ///             context.response.status_code(404).from_text("No such user").ok();
+    ///         }
+    ///         err => {
+    ///             context.response.from_error(&err);
+    ///         }
+    ///     }
+    /// });
+    ///
+    /// let client = app.test_client();
+    ///
+    /// assert_eq!(client.get("/").send().get_status_code(), 500);
+    /// assert_eq!(client.get("/user/12").send().get_body(), b"12");
+    /// assert_eq!(client.get("/user/abc").send().get_status_code(), 404);
+    /// ```
+    pub fn on_error<H>(&mut self, handle: H)
+        where H: Fn(&mut Context, Error) + Send + Sync + 'static
+    {
+        self.on_error = Some(Box::new(handle));
+    }
 
-                                for (key, value) in matches.iter() {
-                                    context.request.params().insert(key.to_owned(), caps.get(*value).unwrap().as_str().to_owned());
-                                }
-                            }
-                        } else {
-                            let pattern = {
-                                let pattern = route.pattern();
-                                if pattern != "/" {
-                                    pattern.trim_end_matches('/').to_owned()
-                                } else {
-                                    pattern.to_owned()
-                                }
-                            };
+    /// Add `panic handle` to app.
+    ///
+    /// When a handle or middleware panics, the context is lost and the
+    /// request is answered with a new response, 500 Internal Server Error
+    /// by default. The hook may change it, knowing the panic message and the
+    /// route. The panic is logged either way, and the server keeps serving
+    /// other requests.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/", |_| -> &'static str {
+    ///     panic!("Oops");
+    /// });
+    ///
+    /// app.on_panic(|response, panic| {
+    ///     let text = format!("{} {}", panic.route.as_ref().unwrap(), panic.message);
+    ///     response.status_code(503).from_text(text).ok();
+    /// });
+    ///
+    /// let response = app.test_client().get("/").send();
+    ///
+    /// assert_eq!(response.get_status_code(), 503);
+    /// assert_eq!(response.get_body(), b"/ Oops");
+    /// ```
+    pub fn on_panic<H>(&mut self, handle: H)
+        where H: Fn(&mut crate::http::Response, &Panic) + Send + Sync + 'static
+    {
+        self.on_panic = Some(Box::new(handle));
+    }
 
-                            if pattern == path {
-                                route_found = true;
-                            }
-                        }
+    /// Log a panic and build the response, with the `panic handle` if any.
+    fn handle_panic(&self, mut panic: Panic, payload: Box<dyn Any + Send>) -> crate::http::Response {
+        panic.message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "Box<dyn Any>".to_owned(),
+            },
+        };
 
-                        if route_found {
+        log::error!(
+            "Handle of {} {} ({}) panicked: {}",
+            panic.method, panic.path, panic.route.as_deref().unwrap_or("no route"), panic.message
+        );
 
-                            for before in self.before.iter() {
-                                before.execute(&mut context);
-                            }
+        let mut response = crate::http::Response::empty(500);
 
-                            for before in group.before.iter() {
-                                before.execute(&mut context);
-                            }
+        let handled = match self.on_panic {
+            Some(ref on_panic) => panic::catch_unwind(AssertUnwindSafe(|| on_panic(&mut response, &panic))).is_ok(),
+            None => false,
+        };
 
-                            route.execute(&mut context);
+        if !handled {
+            response = crate::http::Response::empty(500);
+            response.from_text("Internal Server Error").unwrap();
+        }
 
-                            for after in group.after.iter() {
-                                after.execute(&mut context);
-                            }
+        if panic.method == Method::HEAD {
+            response.strip_body();
+        }
 
-                            for after in self.after.iter() {
-                                after.execute(&mut context);
-                            }
+        response
+    }
 
-                            break 'outer;
-                        }
-                    }
-                }
-            }
+    /// Write an error into the response, with the `error handle` if any.
+    pub(crate) fn handle_error(&self, context: &mut Context, err: Error) {
+        let group = context.group.and_then(|index| {
+            self.lineage(index).into_iter().rev().find_map(|group| group.on_error.as_ref())
+        });
 
-            if !route_found {
-                if let Some(ref not_found) = self.not_found {
-                    not_found.execute(&mut context);
-                } else {
-                    context.response.status_code(404).from_text("Not Found").unwrap();
-                }
+        match group.or(self.on_error.as_ref()) {
+            Some(on_error) => on_error(context, err),
+            None => {
+                context.response.from_error(&err);
             }
         }
+    }
 
-        for finish in self.finish.iter() {
-            finish.execute_always(&mut context);
-        }
+    /// Answer with the not-found handle of the innermost group of the
+    /// request that has one, or of the app.
+    pub(crate) fn handle_not_found(&self, context: &mut Context) {
+        let group = context.group.and_then(|index| {
+            self.lineage(index).into_iter().rev().find_map(|group| group.not_found.as_ref())
+        });
 
-        context.finish()
+        match group.or(self.not_found.as_ref()) {
+            Some(not_found) => not_found.execute(context),
+            None => {
+                context.response.status_code(404).from_text("Not Found").unwrap();
+            }
+        }
     }
 
-    /// Run app.
+    /// Serve the files of a directory under `prefix`.
+    ///
+    /// The content type is guessed from the file extension, a directory
+    /// serves its `index.html`, and paths leaving the directory are not
+    /// found. Responses carry `ETag` and `Last-Modified`, conditional
+    /// requests are answered with 304 Not Modified, and a single `Range`
+    /// with 206 Partial Content.
+    ///
+    /// # Examples
     ///
     /// ```no_run
     /// use sincere::App;
     ///
-    /// fn main() {
-    ///     let mut app = App::new();
+    /// let mut app = App::new();
     ///
-    ///     app.get("/", |context| {
-    ///         context.response.from_text("Hello world!").unwrap();
-    ///     });
+    /// app.static_dir("/assets", "./public");
     ///
-    ///     app.run("0.0.0.0:10001").unwrap();
-    /// }
+    /// app.run("0.0.0.0:10001").unwrap();
     /// ```
-    ///
-    pub fn run(&self, addr: &str) -> Result<()> {
-        let sincere_logo = Print::green(
-        r"
-         __.._..  . __ .___.__ .___
-        (__  | |\ |/  `[__ [__)[__
-        .__)_|_| \|\__.[___|  \[___
-        "
-        );
+    pub fn static_dir<P: AsRef<Path>>(&mut self, prefix: &str, dir: P) -> &mut Route {
+        self.groups.get_mut(0).unwrap().static_dir(prefix, dir)
+    }
 
-        println!("{}", sincere_logo);
-        println!(
-            "    {}{}",
-            Print::green("Server running at http://"),
-            Print::green(addr)
-        );
+    /// Limit request bodies to `size` bytes, there is no limit by default.
+    ///
+    /// A request whose `Content-Length` is over the limit is answered with
+    /// 413 Payload Too Large before its body is read, and reading a body
+    /// without `Content-Length` stops as soon as it goes over. Streaming
+    /// routes see the limit as an `Error::PayloadTooLarge` from the body
+    /// reader. `Group::max_body_size` and `Route::max_body_size` override
+    /// the limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.max_body_size(4);
+    ///
+    /// app.post("/", |context| {
+    ///     context.response.from_text("Hello world!").unwrap();
+    /// });
+    ///
+    /// let response = app.test_client().post("/").body("Hello world!").send();
+    ///
+    /// assert_eq!(response.get_status_code(), 413);
+    /// ```
+    pub fn max_body_size(&mut self, size: usize) -> &mut App {
+        self.max_body_size = Some(size);
+        self
+    }
 
-        let app = unsafe {
-            let a: *const App = &*self;
-            &*a
-        };
+    /// Set the secret that signed and private cookies are keyed from.
+    ///
+    /// It must be kept out of the code, and stay the same across restarts
+    /// and servers for cookies to remain readable.
+    ///
+    /// # Panics
+    ///
+    /// If the secret is shorter than 32 bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.secret_key(b"an app secret of at least 32 bytes, kept out of the repository");
+    /// ```
+    pub fn secret_key(&mut self, secret: &[u8]) -> &mut App {
+        self.secret_key = Some(Key::derive(secret));
+        self
+    }
 
-        let echo = move |req: Request<Body>| async move {
+    /// Add `method-not-allowed handle` to app.
+    ///
+    /// It is called when the path matches routes registered for other
+    /// methods only. The status code is already set to 405, and the `Allow`
+    /// header to the methods of those routes, plus `HEAD` and `OPTIONS`
+    /// which are answered automatically.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.method_not_allowed(|context| {
+    ///     context.response.from_text("Method Not Allowed!").unwrap();
+    /// });
+    /// ```
+    pub fn method_not_allowed<H, R>(&mut self, handle: H)
+        where H: Fn(&mut Context) -> R + Send + Sync + 'static,
+              R: IntoResponse
+    {
+        self.method_not_allowed = Some(Hook::new(handle));
+    }
 
-            let (parts, body) = req.into_parts();
-            let body = hyper::body::to_bytes(body).await?;
+    /// Create an in-process test client for the app, requests sent through it
+    /// skip the TCP socket.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/", |context| {
+    ///     context.response.from_text("Hello world!").unwrap();
+    /// });
+    ///
+    /// let client = app.test_client();
+    ///
+    /// let response = client.get("/").send();
+    ///
+    /// assert_eq!(response.get_status_code(), 200);
+    /// assert_eq!(response.get_body(), b"Hello world!");
+    /// ```
+    pub fn test_client(self) -> TestClient {
+        TestClient::new(self)
+    }
 
-            let join = task::spawn_blocking(move || {
-                app.handle(parts, body)
-            });
+    /// handle
+    pub(crate) async fn handle(app: Arc<App>, parts: Parts, body: Body) -> Response<Body> {
+        App::dispatch(app, parts, body).await.raw_response()
+    }
 
-            let res = join.await.expect("The task being joined has panicked");
+    /// Drive a request through the middlewares and routes, and return the
+    /// response before it is converted into a hyper response.
+    ///
+    /// Synchronous handlers run with their middlewares in a single blocking
+    /// task, asynchronous handlers are awaited on the runtime between the
+    /// blocking `before` and `after` stages.
+    ///
+    /// The route is found before the body is read, so that a streaming route
+    /// gets the body as it arrives.
+    pub(crate) async fn dispatch(app: Arc<App>, parts: Parts, body: Body) -> crate::http::Response {
+        let found = app.find_route(&parts.method, parts.uri.path());
+
+        let (streaming, limit) = match found {
+            Some((ref id, _)) => (app.route(id).is_streaming(), app.body_limit(id)),
+            None => (false, app.max_body_size)
+        };
+
+        if let Some(limit) = limit {
+            let length = parts.headers.get(hyper::header::CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok())
+                .and_then(|length| length.parse::<usize>().ok());
+
+            if length.is_some_and(|length| length > limit) {
+                return payload_too_large();
+            }
+        }
+
+        let (payload, rejected) = if streaming {
+            (Payload::Stream(body), None)
+        } else {
+            match BodyStream::new(body, limit).bytes().await {
+                Ok(body) => (Payload::Buffered(body), None),
+                Err(Error::PayloadTooLarge(_)) => return payload_too_large(),
+                Err(err) => {
+                    let err = Error::BadRequest(format!("Can't read the request body: {}", err));
+                    (Payload::Buffered(Default::default()), Some(err))
+                }
+            }
+        };
+
+        let mut context = Context::new(app.clone(), parts, payload, limit);
+
+        let rejected = match rejected {
+            Some(err) => Some(err),
+            None => context.request.parse_post().err(),
+        };
+
+        let id = found.map(|(id, params)| {
+            for (key, value) in params {
+                context.request.params().insert(key, value);
+            }
+
+            id
+        });
+
+        context.group = match id {
+            Some(ref id) => Some(id.group),
+            None => app.group_of(context.request.uri().path()),
+        };
+
+        let panic = Panic {
+            message: String::new(),
+            method: context.request.method().clone(),
+            path: context.request.uri().path().to_owned(),
+            route: id.as_ref().map(|id| app.route(id).pattern().clone()),
+        };
+
+        let app2 = app.clone();
+
+        if let Some(err) = rejected {
+            return match blocking(move || app2.reject(context, err)).await {
+                Ok(context) => context.finish(),
+                Err(payload) => app.handle_panic(panic, payload)
+            };
+        }
+
+        let app2 = app.clone();
+
+        let (context, id) = match blocking(move || app2.handle_head(context, id)).await {
+            Ok(Stage::Done(context)) => return context.finish(),
+            Ok(Stage::Await(context, id)) => (context, id),
+            Err(payload) => return app.handle_panic(panic, payload)
+        };
+
+        let context = match catch_unwind(app.route(&id).call_async(context)).await {
+            Ok(context) => context,
+            Err(payload) => return app.handle_panic(panic, payload)
+        };
+
+        let app2 = app.clone();
+
+        let result = blocking(move || {
+            let mut context = context;
+            app2.handle_tail(&mut context, &id);
+            context
+        }).await;
+
+        match result {
+            Ok(context) => context.finish(),
+            Err(payload) => app.handle_panic(panic, payload)
+        }
+    }
+
+    /// Handle the request up to the asynchronous handle of a staged route,
+    /// or entirely.
+    fn handle_head(&self, mut context: Context, id: Option<RouteId>) -> Stage {
+        match id {
+            Some(id) if self.is_staged(&id) => {
+                self.outer_hooks().run_before(&mut context);
+                self.hooks().run_before(&mut context);
+
+                for group in self.lineage(id.group) {
+                    group.outer_hooks().run_before(&mut context);
+                    group.hooks().run_before(&mut context);
+                }
+
+                self.route(&id).hooks().run_before(&mut context);
+
+                Stage::Await(context, id)
+            }
+            id => {
+                self.run_chain(&mut context, id.as_ref());
+
+                Stage::Done(context)
+            }
+        }
+    }
+
+    /// Run what follows the asynchronous handle of a staged route.
+    fn handle_tail(&self, context: &mut Context, id: &RouteId) {
+        self.route(id).hooks().run_after(context);
+
+        for group in self.lineage(id.group).into_iter().rev() {
+            group.hooks().run_after(context);
+            group.outer_hooks().run_after(context);
+        }
+
+        self.hooks().run_after(context);
+        self.outer_hooks().run_after(context);
+    }
+
+    /// Run the chain of the route, or the fallback if there is none: the
+    /// `begin` and `finish` hooks, the app middleware, and for a route the
+    /// `before` and `after` hooks of the app, then the `begin` and `finish`
+    /// hooks, middleware and `before` and `after` hooks of its groups from
+    /// the outermost, and the middleware and hooks of the route around its
+    /// handle.
+    fn run_chain(&self, context: &mut Context, id: Option<&RouteId>) {
+        let outer = self.outer_hooks();
+        let hooks = self.hooks();
 
-            Ok::<_, hyper::Error>(res)
+        let mut chain: Vec<&dyn Middleware> = vec![&outer];
+        chain.extend(self.middleware.iter().map(|middleware| &**middleware));
+
+        let id = match id {
+            Some(id) => id,
+            None => {
+                let fallback = |context: &mut Context| self.fallback(context);
+                return Next::new(&chain, &fallback).call(context);
+            }
         };
 
-        let service = make_service_fn(|_| async move { Ok::<_, hyper::Error>(service_fn(echo)) });
+        let lineage = self.lineage(id.group);
+        let route = self.route(id);
+
+        let group_hooks: Vec<(Hooks, Hooks)> = lineage
+            .iter()
+            .map(|group| (group.outer_hooks(), group.hooks()))
+            .collect();
+        let route_hooks = route.hooks();
 
-        let mut rt = Runtime::new()?;
+        chain.push(&hooks);
+
+        for (group, (outer, hooks)) in lineage.iter().zip(group_hooks.iter()) {
+            chain.push(outer);
+            chain.extend(group.get_middleware().iter().map(|middleware| &**middleware));
+            chain.push(hooks);
+        }
+
+        chain.extend(route.get_middleware().iter().map(|middleware| &**middleware));
+        chain.push(&route_hooks);
+
+        let endpoint = |context: &mut Context| route.call(context);
+
+        Next::new(&chain, &endpoint).call(context);
+    }
 
-        rt.block_on(async {
-            let addr = addr.parse().expect("Address is not valid");
+    /// Answer a request whose body can't be read or parsed with its error,
+    /// inside the `begin` and `finish` hooks and the app middleware.
+    fn reject(&self, mut context: Context, err: Error) -> Context {
+        let outer = self.outer_hooks();
 
-            let server = Server::bind(&addr).serve(service);
+        let mut chain: Vec<&dyn Middleware> = vec![&outer];
+        chain.extend(self.middleware.iter().map(|middleware| &**middleware));
 
-            if let Err(e) = server.await {
-                eprintln!("server error: {}", e);
+        let err = Mutex::new(Some(err));
+
+        let endpoint = |context: &mut Context| {
+            if let Some(err) = err.lock().unwrap().take() {
+                context.fail(err);
+            }
+        };
+
+        Next::new(&chain, &endpoint).call(&mut context);
+
+        context
+    }
+
+    /// Answer a request no route matched, with the allowed methods of the
+    /// path if any.
+    fn fallback(&self, context: &mut Context) {
+        let allowed = self.allowed_methods(context.request.uri().path());
+
+        if !allowed.is_empty() {
+            let allowed: Vec<&str> = allowed.iter().map(|m| m.as_str()).collect();
+
+            if let Ok(allow) = HeaderValue::from_str(&allowed.join(", ")) {
+                context.response.headers_mut().insert(header::ALLOW, allow);
             }
+
+            if context.request.method() == Method::OPTIONS {
+                context.response.status_code(204);
+            } else if let Some(ref method_not_allowed) = self.method_not_allowed {
+                context.response.status_code(405);
+                method_not_allowed.execute(context);
+            } else {
+                context.response.status_code(405).from_text("Method Not Allowed").unwrap();
+            }
+        } else {
+            self.handle_not_found(context);
+        }
+    }
+
+    /// Whether the asynchronous handle of a route is awaited on the runtime
+    /// between its hooks. A middleware can't run around it, so a wrapped one
+    /// is awaited in place on the blocking thread instead.
+    fn is_staged(&self, id: &RouteId) -> bool {
+        let route = self.route(id);
+
+        route.is_async()
+            && self.middleware.is_empty()
+            && self.lineage(id.group).iter().all(|group| group.get_middleware().is_empty())
+            && route.get_middleware().is_empty()
+    }
+
+    fn outer_hooks(&self) -> Hooks<'_> {
+        Hooks {
+            before: &self.begin,
+            after: &self.finish,
+            always: true,
+        }
+    }
+
+    fn hooks(&self) -> Hooks<'_> {
+        Hooks {
+            before: &self.before,
+            after: &self.after,
+            always: false,
+        }
+    }
+
+    /// Find the route of the request, a `HEAD` request falls back to the
+    /// `GET` route of the path.
+    fn find_route(&self, method: &Method, path: &str) -> Option<(RouteId, Vec<(String, String)>)> {
+        let found = match self.router.find(method, path) {
+            Some(found) => found,
+            None if method == Method::HEAD => self.router.find(&Method::GET, path)?,
+            None => return None,
+        };
+
+        Some((found.value.clone(), found.params))
+    }
+
+    /// The methods a path answers to, `HEAD` and `OPTIONS` included when they
+    /// are derived from the registered routes.
+    fn allowed_methods(&self, path: &str) -> Vec<Method> {
+        let mut methods = self.router.methods(path);
+
+        if methods.is_empty() {
+            return methods;
+        }
+
+        if methods.contains(&Method::GET) && !methods.contains(&Method::HEAD) {
+            methods.push(Method::HEAD);
+        }
+
+        if !methods.contains(&Method::OPTIONS) {
+            methods.push(Method::OPTIONS);
+        }
+
+        let order = [
+            Method::GET, Method::HEAD, Method::POST, Method::PUT, Method::PATCH,
+            Method::DELETE, Method::CONNECT, Method::OPTIONS, Method::TRACE
+        ];
+
+        methods.sort_by_key(|method| {
+            order.iter().position(|m| m == method).unwrap_or(order.len())
         });
 
-        Ok(())
+        methods
     }
+
+    /// Build the route tree from the groups, called once the app is frozen.
+    ///
+    /// Routes of earlier groups, then earlier routes, win over later ones
+    /// with the same pattern and method.
+    pub(crate) fn build_router(&mut self) {
+        let mut router = Router::new();
+
+        for (group_index, group) in self.groups.iter().enumerate() {
+            for (method, routes) in group.routes.iter() {
+                for (route_index, route) in routes.iter().enumerate() {
+                    let id = RouteId {
+                        group: group_index,
+                        method: method.clone(),
+                        index: route_index,
+                    };
+
+                    if route.is_async() && !self.is_staged(&id) {
+                        log::warn!(
+                            "Async handle of {} {} is wrapped in middleware, it runs on a blocking thread",
+                            method, route.pattern()
+                        );
+                    }
+
+                    router.insert_pattern(method.clone(), &route.compiled, id);
+                }
+            }
+        }
+
+        self.router = router;
+    }
+
+    fn route(&self, id: &RouteId) -> &Route {
+        &self.groups[id.group].routes[&id.method][id.index]
+    }
+
+    /// The body size limit of a route, the most specific one wins.
+    fn body_limit(&self, id: &RouteId) -> Option<usize> {
+        self.route(id).get_max_body_size()
+            .or_else(|| self.lineage(id.group).into_iter().rev().find_map(|group| group.get_max_body_size()))
+            .or(self.max_body_size)
+    }
+
+    /// Run app.
+    ///
+    /// ```no_run
+    /// use sincere::App;
+    ///
+    /// fn main() {
+    ///     let mut app = App::new();
+    ///
+    ///     app.get("/", |context| {
+    ///         context.response.from_text("Hello world!").unwrap();
+    ///     });
+    ///
+    ///     app.run("0.0.0.0:10001").unwrap();
+    /// }
+    /// ```
+    ///
+    pub fn run(self, addr: &str) -> Result<()> {
+        self.into_server().run(addr)
+    }
+
+    /// Run app until the `signal` future completes, then shut down gracefully.
+    ///
+    /// Once the signal fires the server stops accepting connections, and waits
+    /// for in-flight requests, including handlers still running on the blocking
+    /// pool, to finish before returning.
+    ///
+    /// ```no_run
+    /// use sincere::App;
+    /// use sincere::app::shutdown_signal;
+    ///
+    /// fn main() {
+    ///     let mut app = App::new();
+    ///
+    ///     app.get("/", |context| {
+    ///         context.response.from_text("Hello world!").unwrap();
+    ///     });
+    ///
+    ///     app.run_with_shutdown("0.0.0.0:10001", shutdown_signal()).unwrap();
+    /// }
+    /// ```
+    ///
+    /// Or trigger it from a oneshot channel:
+    ///
+    /// ```no_run
+    /// use std::thread;
+    ///
+    /// use sincere::App;
+    /// use tokio::sync::oneshot;
+    ///
+    /// let (tx, rx) = oneshot::channel::<()>();
+    ///
+    /// let server = thread::spawn(move || {
+    ///     let app = App::new();
+    ///
+    ///     app.run_with_shutdown("0.0.0.0:10001", async {
+    ///         rx.await.ok();
+    ///     }).unwrap();
+    /// });
+    ///
+    /// tx.send(()).unwrap();
+    /// server.join().unwrap();
+    /// ```
+    pub fn run_with_shutdown<F>(self, addr: &str, signal: F) -> Result<()>
+        where F: Future<Output = ()>
+    {
+        self.into_server().run_with_shutdown(addr, signal)
+    }
+
+    /// Freeze the app into a `Server`, which shares it behind an `Arc`.
+    ///
+    /// ```no_run
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/", |context| {
+    ///     context.response.from_text("Hello world!").unwrap();
+    /// });
+    ///
+    /// let server = app.into_server();
+    ///
+    /// let handle = server.spawn("127.0.0.1:10001").unwrap();
+    ///
+    /// server.run("127.0.0.1:10002").unwrap();
+    ///
+    /// handle.shutdown().unwrap();
+    /// ```
+    pub fn into_server(self) -> Server {
+        Server::new(self)
+    }
+}
+
+fn payload_too_large() -> crate::http::Response {
+    let mut response = crate::http::Response::empty(413);
+    response.from_text("Payload Too Large").unwrap();
+    response
+}
+
+/// Run a stage on the blocking pool, catching a panic.
+async fn blocking<F, T>(stage: F) -> thread::Result<T>
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static
+{
+    match task::spawn_blocking(move || panic::catch_unwind(AssertUnwindSafe(stage))).await {
+        Ok(result) => result,
+        Err(err) => Err(Box::new(err.to_string()))
+    }
+}
+
+/// A panic of a handle or middleware, see `App::on_panic`.
+#[derive(Debug, Clone)]
+pub struct Panic {
+    /// The panic message.
+    pub message: String,
+    pub method: Method,
+    pub path: String,
+    /// The pattern of the route, if one was found.
+    pub route: Option<String>,
+}
+
+/// Position of a route in the app.
+#[derive(Clone)]
+struct RouteId {
+    group: usize,
+    method: Method,
+    index: usize,
+}
+
+/// Where `App::handle_head` stopped.
+enum Stage {
+    /// The response is complete.
+    Done(Context),
+    /// The asynchronous handler of the route is still to be awaited.
+    Await(Context, RouteId),
 }
 
-// pub fn leak<T>(v: T) -> &'static T {
-//     unsafe {
-//         let b = Box::new(v);
-//         let p: *const T = &*b;
-//         std::mem::forget(b); // leak our reference, so that `b` is never freed
-//         &*p
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::App;
+    use crate::error::Result;
+
+    #[test]
+    fn async_handle_with_middlewares() {
+        let mut app = App::new();
+
+        app.before(|context| {
+            if context.request.header("Token").is_none() {
+                context.response.status_code(401);
+                context.stop();
+            }
+        });
+
+        app.get_async("/", |mut context| async move {
+            let text = async { "Hello world!" }.await;
+            context.response.from_text(text).unwrap();
+            context
+        });
+
+        app.after(|context| -> Result<()> {
+            context.response.header(("X-After", "true"))?;
+            Ok(())
+        });
+
+        let client = app.test_client();
+
+        let response = client.get("/").header("Token", "token").send();
+        assert_eq!(response.get_status_code(), 200);
+        assert_eq!(response.get_body(), b"Hello world!");
+        assert_eq!(response.get_header("X-After").unwrap(), "true");
+
+        let response = client.get("/").send();
+        assert_eq!(response.get_status_code(), 401);
+        assert!(response.get_body().is_empty());
+    }
+
+    #[test]
+    fn async_errors() {
+        let mut app = App::new();
+
+        app.post_async("/", |mut context| async move {
+            let result: Result<()> = async {
+                let _: serde_json::Value = context.request.bind_json()?;
+                Ok(())
+            }.await;
+
+            (context, result)
+        });
+
+        app.get_async("/", |context| async move {
+            (context, Err::<(), _>(crate::Error::Error("Oops".to_owned())))
+        });
+
+        app.on_error(|context, err| {
+            let text = format!("handled: {}", err.status_code());
+            context.response.status_code(err.status_code()).from_text(text).unwrap();
+        });
+
+        let client = app.test_client();
+
+        let response = client.post("/").body("{").send();
+        assert_eq!(response.get_status_code(), 400);
+        assert_eq!(response.get_body(), b"handled: 400");
+
+        let response = client.post("/").body("{}").send();
+        assert_eq!(response.get_status_code(), 200);
+
+        let response = client.get("/").send();
+        assert_eq!(response.get_status_code(), 500);
+        assert_eq!(response.get_body(), b"handled: 500");
+    }
+
+    #[test]
+    fn onion() {
+        use super::middleware::from_fn;
+
+        fn trace(context: &mut super::Context, step: &str) -> Result<()> {
+            let trace = context.response.get_header("X-Trace").unwrap_or_default().to_owned();
+            context.response.header(("X-Trace", trace + step))?;
+            Ok(())
+        }
+
+        let mut app = App::new();
+
+        app.begin(|context| trace(context, "b"));
+        app.before(|context| trace(context, "<"));
+        app.after(|context| trace(context, ">"));
+        app.finish(|context| trace(context, "f"));
+
+        app.wrap(from_fn(|context, next| {
+            trace(context, "(")?;
+            next.run(context);
+            trace(context, ")")
+        }));
+
+        app.mount("/group", |group| {
+            group.wrap(from_fn(|context, next| {
+                trace(context, "[")?;
+                next.run(context);
+                trace(context, "]")
+            }));
+
+            group.get("/", |context| trace(context, "h"))
+                .wrap(from_fn(|context, next| {
+                    trace(context, "{")?;
+                    next.run(context);
+                    trace(context, "}")
+                }));
+
+            group.get_async("/async", |mut context| async move {
+                let step = async { "a" }.await;
+                trace(&mut context, step).unwrap();
+                context
+            });
+
+            group.get("/denied", |context| trace(context, "h"))
+                .wrap(from_fn(|context, _next| trace(context, "!")));
+
+            group.get("/failed", |context| trace(context, "h"))
+                .wrap(from_fn(|_, _| Err(crate::Error::Error("Oops".to_owned()))));
+        });
+
+        let client = app.test_client();
+
+        let response = client.get("/group/").send();
+        assert_eq!(response.get_header("X-Trace"), Some("b(<[{h}]>)f"));
+
+        let response = client.get("/group/async").send();
+        assert_eq!(response.get_header("X-Trace"), Some("b(<[a]>)f"));
+
+        let response = client.get("/group/denied").send();
+        assert_eq!(response.get_header("X-Trace"), Some("b(<[!]>)f"));
+
+        let response = client.get("/group/failed").send();
+        assert_eq!(response.get_status_code(), 500);
+        assert_eq!(response.get_header("X-Trace"), Some("b(<[])f"));
+
+        let response = client.get("/missing").send();
+        assert_eq!(response.get_status_code(), 404);
+        assert_eq!(response.get_header("X-Trace"), Some("b()f"));
+    }
+
+    #[test]
+    fn nested_groups() {
+        use super::Group;
+        use super::middleware::from_fn;
+
+        fn trace(context: &mut super::Context, step: &str) -> Result<()> {
+            let trace = context.response.get_header("X-Trace").unwrap_or_default().to_owned();
+            context.response.header(("X-Trace", trace + step))?;
+            Ok(())
+        }
+
+        let mut admin = Group::new("/admin");
+
+        admin.begin(|context| trace(context, "{"));
+        admin.before(|context| trace(context, "admin"));
+        admin.after(|context| trace(context, ","));
+        admin.finish(|context| trace(context, "}"));
+        admin.get_async("/async", |mut context| async move {
+            let result = trace(&mut context, ",async");
+            (context, result)
+        });
+        admin.get("/fail", |_| -> Result<()> { Err(crate::Error::Error("Oops".to_owned())) });
+        admin.post("/upload", |_| "Uploaded!");
+
+        let mut app = App::new();
+
+        app.on_error(|context, _| {
+            context.response.status_code(500).from_text("app error").unwrap();
+        });
+
+        app.mount("/api", |api| {
+            api.begin(|context| trace(context, "("));
+            api.finish(|context| trace(context, ")"));
+
+            api.wrap(from_fn(|context, next| {
+                trace(context, "api,")?;
+                next.run(context);
+                Ok(())
+            }));
+
+            api.max_body_size(4);
+
+            api.not_found(|context| {
+                context.response.status_code(404).from_text("api not found").unwrap();
+            });
+
+            api.mount("/v1", |v1| {
+                v1.before(|context| trace(context, "v1,"));
+                v1.get("/users", |context| trace(context, ",users"));
+
+                v1.on_error(|context, _| {
+                    context.response.status_code(500).from_text("v1 error").unwrap();
+                });
+            });
+        });
+
+        // the same prefix as a nested group, but inheriting nothing from it
+        let mut outer = Group::new("/api/v1");
+        outer.mount_group(admin);
+        app.mount_group(outer);
+
+        app.get("/fail", |_| -> Result<()> { Err(crate::Error::Error("Oops".to_owned())) });
+
+        let client = app.test_client();
+
+        let response = client.get("/api/v1/users").send();
+        assert_eq!(response.get_header("X-Trace"), Some("(api,v1,,users)"));
+
+        let response = client.get("/api/v2").send();
+        assert_eq!(response.get_body(), b"api not found");
+
+        let response = client.get("/api/v1/missing").send();
+        assert_eq!(response.get_body(), b"api not found");
+
+        let response = client.get("/apiary").send();
+        assert_eq!(response.get_body(), b"Not Found");
+
+        let response = client.get("/api/v1/admin/fail").send();
+        assert_eq!(response.get_header("X-Trace"), Some("{admin}"));
+        assert_eq!(response.get_body(), b"app error");
+
+        let response = client.get("/api/v1/admin/async").send();
+        assert_eq!(response.get_header("X-Trace"), Some("{admin,async,}"));
+        assert_eq!(response.get_status_code(), 200);
+
+        let response = client.post("/api/v1/admin/upload").body("big body").send();
+        assert_eq!(response.get_status_code(), 200);
+
+        let response = client.get("/fail").send();
+        assert_eq!(response.get_body(), b"app error");
+    }
+
+    #[test]
+    fn method_not_allowed() {
+        let mut app = App::new();
+
+        app.get("/user/{id}", |context| {
+            context.response.from_text("Get user!").unwrap();
+        });
+
+        app.mount("/user", |group| {
+            group.delete("/{id:[0-9]+}", |context| {
+                context.response.from_text("Delete user!").unwrap();
+            });
+        });
+
+        let client = app.test_client();
+
+        let response = client.post("/user/12").send();
+        assert_eq!(response.get_status_code(), 405);
+        assert_eq!(response.get_header("Allow").unwrap(), "GET, HEAD, DELETE, OPTIONS");
+
+        let response = client.post("/user/abc").send();
+        assert_eq!(response.get_status_code(), 405);
+        assert_eq!(response.get_header("Allow").unwrap(), "GET, HEAD, OPTIONS");
+
+        assert_eq!(client.post("/post").send().get_status_code(), 404);
+
+        let mut app = App::new();
+
+        app.get("/", |_| {});
+
+        app.method_not_allowed(|context| {
+            let allow = context.response.get_header("Allow").unwrap().to_owned();
+            context.response.from_text(allow).unwrap();
+        });
+
+        let response = app.test_client().put("/").send();
+        assert_eq!(response.get_status_code(), 405);
+        assert_eq!(response.get_body(), b"GET, HEAD, OPTIONS");
+    }
+
+    #[test]
+    fn head_and_options() {
+        let mut app = App::new();
+
+        app.get("/", |context| {
+            context.response.from_text("Hello world!").unwrap();
+        });
+
+        app.post("/", |_| {});
+
+        app.options("/custom", |context| -> Result<()> {
+            context.response.header(("Allow", "OPTIONS"))?;
+            Ok(())
+        });
+
+        let client = app.test_client();
+
+        let response = client.head("/").send();
+        assert_eq!(response.get_status_code(), 200);
+        assert_eq!(response.get_header("Content-Length").unwrap(), "12");
+        assert_eq!(response.get_header("Content-Type").unwrap(), "text/plain; charset=UTF-8");
+        assert!(response.get_body().is_empty());
+
+        let response = client.options("/").send();
+        assert_eq!(response.get_status_code(), 204);
+        assert_eq!(response.get_header("Allow").unwrap(), "GET, HEAD, POST, OPTIONS");
+
+        let response = client.options("/custom").send();
+        assert_eq!(response.get_status_code(), 200);
+        assert_eq!(response.get_header("Allow").unwrap(), "OPTIONS");
+
+        assert_eq!(client.options("/none").send().get_status_code(), 404);
+    }
+
+    #[test]
+    fn streaming_and_buffered_bodies() {
+        let mut app = App::new();
+
+        app.post("/buffered", |context| {
+            assert!(!context.request.is_streaming());
+            assert!(context.request.body_reader().is_none());
+
+            let name = context.request.post("name").unwrap_or_default();
+            context.response.from_text(name).unwrap();
+        });
+
+        app.post_async("/streaming/{id}", |mut context| async move {
+            assert!(context.request.posts().is_empty());
+
+            let id = context.request.param("id").unwrap();
+            let body = context.request.body_stream().unwrap().bytes().await.unwrap();
+
+            context.response.from_text(format!("{}:{}", id, body.len())).unwrap();
+            context
+        }).streaming();
+
+        let client = app.test_client();
+
+        let response = client.post("/buffered").form(&[("name", "sincere")]).send();
+        assert_eq!(response.get_body(), b"sincere");
+
+        let response = client.post("/streaming/1").form(&[("name", "sincere")]).send();
+        assert_eq!(response.get_body(), b"1:12");
+    }
+
+    #[test]
+    fn max_body_size() {
+        use std::io::Read;
+
+        let mut app = App::new();
+
+        app.max_body_size(8);
+
+        app.post("/", |_| {});
+
+        app.post("/large", |_| {}).max_body_size(64);
+
+        app.post("/stream", |context| {
+            let mut data = Vec::new();
+            let result = context.request.body_reader().unwrap().read_to_end(&mut data);
+
+            if result.is_err() {
+                context.response.status_code(413);
+            }
+        }).streaming();
+
+        app.mount("/group", |group| {
+            group.max_body_size(16);
+            group.post("/", |_| {});
+        });
+
+        let client = app.test_client();
+
+        assert_eq!(client.post("/").body(vec![0; 8]).send().get_status_code(), 200);
+        assert_eq!(client.post("/").body(vec![0; 9]).send().get_status_code(), 413);
+        assert_eq!(client.post("/large").body(vec![0; 64]).send().get_status_code(), 200);
+        assert_eq!(client.post("/group/").body(vec![0; 16]).send().get_status_code(), 200);
+        assert_eq!(client.post("/group/").body(vec![0; 17]).send().get_status_code(), 413);
+        assert_eq!(client.post("/none").body(vec![0; 9]).send().get_status_code(), 413);
+
+        // Content-Length is checked before the body is read.
+        let response = client.post("/large").header("Content-Length", "65").send();
+        assert_eq!(response.get_status_code(), 413);
+
+        // Streaming routes find the limit while reading.
+        let response = client.post("/stream").body(vec![0; 9]).send();
+        assert_eq!(response.get_status_code(), 413);
+    }
+
+    #[test]
+    fn unreadable_body() {
+        use std::sync::Arc;
+
+        let mut app = App::new();
+
+        app.begin(|context| -> Result<()> {
+            context.response.header(("X-Begin", "1"))?;
+            Ok(())
+        });
+
+        app.finish(|context| -> Result<()> {
+            context.response.header(("X-Finish", "1"))?;
+            Ok(())
+        });
+
+        app.on_error(|context, err| {
+            context.response.status_code(err.status_code()).from_text(err.to_string()).unwrap();
+        });
+
+        app.post("/", |_| "Posted!");
+
+        let (sender, body) = hyper::Body::channel();
+        sender.abort();
+
+        let (parts, _) = http::Request::post("/").body(()).unwrap().into_parts();
+
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let response = runtime.block_on(async move {
+            let mut response = App::dispatch(Arc::new(app), parts, body).await;
+            response.buffer().await.unwrap();
+            response
+        });
+
+        assert_eq!(response.get_status_code(), 400);
+        assert!(response.get_body().starts_with(b"Can't read the request body"));
+        assert_eq!(response.get_header("X-Begin"), Some("1"));
+        assert_eq!(response.get_header("X-Finish"), Some("1"));
+    }
+
+    #[test]
+    fn errors() {
+        use serde::Deserialize;
+
+        use crate::error::{Error, Result};
+
+        #[derive(Deserialize)]
+        struct User {
+            name: String,
+        }
+
+        let mut app = App::new();
+
+        app.before(|context| -> Result<()> {
+            if context.request.header("Token").is_some_and(|token| token != "token") {
+                return Err(Error::Error("Bad token".to_owned()));
+            }
+
+            Ok(())
+        });
+
+        app.post("/json", |context| -> Result<_> {
+            let user: User = context.request.bind_json()?;
+            Ok(user.name)
+        });
+
+        app.post("/form", |context| -> Result<_> {
+            let user: User = context.request.bind()?;
+            Ok(user.name)
+        });
+
+        app.after(|context| -> Result<()> {
+            context.response.header(("X-After", "true"))?;
+            Ok(())
+        });
+
+        app.finish(|context| -> Result<()> {
+            context.response.header(("X-Finish", "true"))?;
+            Ok(())
+        });
+
+        let client = app.test_client();
+
+        let response = client.post("/json").body(r#"{"name": "json"}"#).send();
+        assert_eq!(response.get_body(), b"json");
+        assert_eq!(response.get_header("X-After").unwrap(), "true");
+
+        let response = client.post("/json").body("{").send();
+        assert_eq!(response.get_status_code(), 400);
+        assert_eq!(response.get_body(), br#"{"location":"body","message":"EOF while parsing an object at line 1 column 1"}"#);
+        assert!(response.get_header("X-After").is_none());
+        assert_eq!(response.get_header("X-Finish").unwrap(), "true");
+
+        let response = client.post("/form").form(&[("age", "1")]).send();
+        assert_eq!(response.get_status_code(), 400);
+        assert_eq!(response.get_body(), br#"{"location":"body","message":"missing field `name`"}"#);
+
+        let response = client.post("/json").header("Token", "none").body(r#"{"name": "json"}"#).send();
+        assert_eq!(response.get_status_code(), 500);
+        assert_eq!(response.get_body(), b"Internal Server Error");
+
+        let mut app = App::new();
+
+        app.get("/", |_| Err::<(), _>(Error::PayloadTooLarge(1)));
+
+        app.on_error(|context, err| {
+            context.response.status_code(err.status_code()).from_text("Oops").ok();
+        });
+
+        let response = app.test_client().get("/").send();
+        assert_eq!(response.get_status_code(), 413);
+        assert_eq!(response.get_body(), b"Oops");
+    }
+
+    #[test]
+    fn panics() {
+        use std::sync::{Arc, Mutex};
+
+        let mut app = App::new();
+
+        app.get("/", |_| "Hello world!");
+
+        app.get("/sync", |_| -> &'static str {
+            panic!("sync");
+        });
+
+        app.get_async("/async", |context| async move {
+            if context.request.query("panic").is_some() {
+                panic!("async {}", 1);
+            }
+
+            context
+        });
+
+        app.get("/before", |_| {}).before(|_| -> &'static str {
+            panic!("before");
+        });
+
+        let client = app.test_client();
+
+        for path in &["/sync", "/async?panic", "/before"] {
+            let response = client.get(path).send();
+            assert_eq!(response.get_status_code(), 500);
+            assert_eq!(response.get_body(), b"Internal Server Error");
+        }
+
+        assert_eq!(client.get("/").send().get_body(), b"Hello world!");
+        assert_eq!(client.get("/async").send().get_status_code(), 200);
+
+        let response = client.head("/sync").send();
+        assert_eq!(response.get_status_code(), 500);
+        assert!(response.get_body().is_empty());
+
+        let panics = Arc::new(Mutex::new(Vec::new()));
+        let panics2 = panics.clone();
+
+        let mut app = App::new();
+
+        app.get("/user/{id}", |_| -> &'static str {
+            panic!("user");
+        });
+
+        app.get("/twice", |context| {
+            if context.request.query("twice").is_some() {
+                panic!("twice");
+            }
+        });
+
+        app.on_panic(move |response, panic| {
+            panics2.lock().unwrap().push(panic.clone());
+
+            if panic.message == "twice" {
+                panic!("again");
+            }
+
+            response.status_code(503);
+        });
+
+        let client = app.test_client();
+
+        assert_eq!(client.get("/user/1").send().get_status_code(), 503);
+        assert_eq!(client.get("/twice?twice").send().get_status_code(), 500);
+
+        let panics = panics.lock().unwrap();
+        assert_eq!(panics[0].message, "user");
+        assert_eq!(panics[0].path, "/user/1");
+        assert_eq!(panics[0].route.as_deref(), Some("/user/{id}"));
+        assert_eq!(panics[1].message, "twice");
+    }
+}
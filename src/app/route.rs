@@ -1,39 +1,57 @@
 //! Route
-use std::collections::HashMap;
-
 use hyper::Method;
-use regex::Regex;
+use tokio::runtime;
 
 use super::context::Context;
-use super::middleware::Middleware;
-use super::Handle;
+use super::middleware::{Hook, Hooks, Middleware};
+use super::router::Pattern;
+use super::{AsyncHandle, Handle};
+
+enum Handler {
+    Sync(Box<Handle>),
+    Async(Box<AsyncHandle>),
+}
 
 /// Route
 pub struct Route {
     pattern: String,
     method: Method,
-    handle: Box<Handle>,
-    pub(crate) regex: Option<Regex>,
-    paths: HashMap<String, usize>,
-    before: Vec<Middleware>,
-    after: Vec<Middleware>,
+    handle: Handler,
+    pub(crate) compiled: Pattern,
+    middleware: Vec<Box<dyn Middleware>>,
+    before: Vec<Hook>,
+    after: Vec<Hook>,
+    streaming: bool,
+    max_body_size: Option<usize>,
 }
 
 impl Route {
     pub fn new(method: Method, pattern: String, handle: Box<Handle>) -> Route {
-        let mut route = Route {
+        Route::with_handler(method, pattern, Handler::Sync(handle))
+    }
+
+    pub fn new_async(method: Method, pattern: String, handle: Box<AsyncHandle>) -> Route {
+        Route::with_handler(method, pattern, Handler::Async(handle))
+    }
+
+    fn with_handler(method: Method, pattern: String, handle: Handler) -> Route {
+        Route {
+            compiled: Pattern::parse(&pattern),
             pattern: pattern,
             method: method,
             handle: handle,
-            regex: None,
-            paths: HashMap::new(),
+            middleware: Vec::new(),
             before: Vec::new(),
             after: Vec::new(),
-        };
-
-        route.re_connfigure();
+            streaming: false,
+            max_body_size: None,
+        }
+    }
 
-        route
+    /// Move the route under a prefix.
+    pub(crate) fn prepend(&mut self, prefix: &str) {
+        self.pattern = prefix.to_owned() + &self.pattern;
+        self.compiled = Pattern::parse(&self.pattern);
     }
 
     pub fn pattern(&self) -> &String {
@@ -44,226 +62,152 @@ impl Route {
         &self.method
     }
 
-    pub fn path(&self) -> HashMap<String, usize> {
-        self.paths.clone()
-    }
-
-    pub fn execute(&self, context: &mut Context) {
-        for before in self.before.iter() {
-            before.execute(context);
+    pub fn is_async(&self) -> bool {
+        match self.handle {
+            Handler::Sync(_) => false,
+            Handler::Async(_) => true,
         }
+    }
 
-        if context.next() {
-            (self.handle)(context);
-        }
+    /// Hand the request body over to the handle as a stream instead of
+    /// reading it into memory first.
+    ///
+    /// The handle takes it with `Request::body_reader` or
+    /// `Request::body_stream`, form data is not parsed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Read;
+    ///
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.post("/upload", |context| {
+    ///     let mut data = Vec::new();
+    ///     context.request.body_reader().unwrap().read_to_end(&mut data).unwrap();
+    ///
+    ///     context.response.from_text(data.len().to_string()).unwrap();
+    /// }).streaming();
+    ///
+    /// let response = app.test_client().post("/upload").body("hello").send();
+    ///
+    /// assert_eq!(response.get_body(), b"5");
+    /// ```
+    pub fn streaming(&mut self) -> &mut Route {
+        self.streaming = true;
+        self
+    }
 
-        for after in self.after.iter() {
-            after.execute(context);
-        }
+    pub fn is_streaming(&self) -> bool {
+        self.streaming
     }
 
-    middleware!(before);
-    middleware!(after);
+    /// Limit the request body of this route to `size` bytes, overriding the
+    /// limit of the group and the app.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.post("/avatar", |context| {
+    ///     context.response.from_text("Saved!").unwrap();
+    /// }).max_body_size(8);
+    ///
+    /// let client = app.test_client();
+    ///
+    /// assert_eq!(client.post("/avatar").body("small").send().get_status_code(), 200);
+    /// assert_eq!(client.post("/avatar").body("too large").send().get_status_code(), 413);
+    /// ```
+    pub fn max_body_size(&mut self, size: usize) -> &mut Route {
+        self.max_body_size = Some(size);
+        self
+    }
 
-    fn re_connfigure(&mut self) {
-        if self.pattern.contains("{") {
-            let (prce_pattern, route_paths) = extract_named_params(&self.pattern);
+    pub fn get_max_body_size(&self) -> Option<usize> {
+        self.max_body_size
+    }
 
-            self.paths = route_paths;
+    /// Wrap the handle in a middleware, run after the app's and group's. An
+    /// asynchronous handle it wraps runs on the blocking pool, see
+    /// `App::wrap`.
+    pub fn wrap<M: Middleware + 'static>(&mut self, middleware: M) -> &mut Route {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
 
-            let compilied_pattern = compile_pattern(prce_pattern);
+    pub(crate) fn get_middleware(&self) -> &[Box<dyn Middleware>] {
+        &self.middleware
+    }
 
-            if compilied_pattern.contains("^") {
-                match Regex::new(&compilied_pattern) {
-                    Ok(regex) => {
-                        self.regex = Some(regex);
-                    }
-                    Err(err) => {
-                        panic!(
-                            "Can't complie route path: {:?}, err: {:?}",
-                            self.pattern, err
-                        );
-                    }
-                }
-            } else {
-                panic!("Can't complie route path: {:?}", self.pattern);
-            }
+    pub(crate) fn hooks(&self) -> Hooks<'_> {
+        Hooks {
+            before: &self.before,
+            after: &self.after,
+            always: false,
         }
     }
-}
-
-fn extract_named_params(pattern: &str) -> (String, HashMap<String, usize>) {
-    let mut parenthese_count = 0;
-    let mut bracket_count = 0;
-    let mut intermediate = 0;
-    let mut marker = 0;
-    let mut number_matches = 0;
-    let mut tmp;
-    let mut found_pattern;
-
-    let mut prev_ch = '\0';
-    let mut variable;
-    let mut regexp;
-    let mut item;
-    let mut route = String::new();
 
-    let mut not_valid = false;
-
-    let mut matches = HashMap::new();
-
-    if !pattern.is_ascii() {
-        panic!("{:?}", "The ruote pattern must be an ascii");
+    pub fn execute(&self, context: &mut Context) {
+        self.execute_before(context);
+        self.call(context);
+        self.execute_after(context);
     }
 
-    for (cursor, ch) in pattern.chars().enumerate() {
-        if parenthese_count == 0 {
-            if ch == '{' {
-                if bracket_count == 0 {
-                    marker = cursor + 1;
-                    intermediate = 0;
-                    not_valid = false;
-                }
-
-                bracket_count += 1;
-            } else {
-                if ch == '}' {
-                    bracket_count -= 1;
-                    if intermediate > 0 {
-                        if bracket_count == 0 {
-                            number_matches += 1;
-                            variable = "";
-                            regexp = "";
-                            item = &pattern[marker..cursor];
-
-                            for (cursor_var, ch) in item.chars().enumerate() {
-                                if ch == '\0' {
-                                    break;
-                                }
-
-                                if cursor_var == 0
-                                    && !((ch >= 'a' && ch <= 'z') || (ch >= 'A' && ch <= 'Z'))
-                                {
-                                    not_valid = true;
-                                    break;
-                                }
-
-                                if (ch >= 'a' && ch <= 'z')
-                                    || (ch >= 'A' && ch <= 'Z')
-                                    || (ch >= '0' && ch <= '9')
-                                    || ch == '-'
-                                    || ch == '_'
-                                    || ch == ':'
-                                {
-                                    if ch == ':' {
-                                        // let (first, last) = item.split_at(cursor_var);
-                                        // variable = first;
-                                        // regexp = &last[1..];
-                                        variable = &item[..cursor_var];
-                                        regexp = &item[cursor_var + 1..];
-                                        break;
-                                    }
-                                } else {
-                                    not_valid = true;
-                                    break;
-                                }
-                            }
-
-                            if !not_valid {
-                                tmp = number_matches;
-                                if !variable.is_empty() && !regexp.is_empty() {
-                                    found_pattern = 0;
-                                    for regexp_ch in regexp.chars() {
-                                        if regexp_ch == '\0' {
-                                            break;
-                                        }
+    pub(crate) fn execute_before(&self, context: &mut Context) {
+        for before in self.before.iter() {
+            before.execute(context);
+        }
+    }
 
-                                        if found_pattern == 0 {
-                                            if regexp_ch == '(' {
-                                                found_pattern = 1;
-                                            }
-                                        } else {
-                                            if regexp_ch == ')' {
-                                                found_pattern = 2;
-                                                break;
-                                            }
-                                        }
-                                    }
+    /// Call the handle, an asynchronous one is awaited in place, blocking
+    /// the thread, which must be one of the runtime's blocking pool.
+    pub(crate) fn call(&self, context: &mut Context) {
+        if !context.next() {
+            return;
+        }
 
-                                    if found_pattern != 2 {
-                                        route.push('(');
-                                        route += regexp;
-                                        route.push(')');
-                                    } else {
-                                        route += regexp;
-                                    }
-                                    matches.insert(variable.to_string(), tmp);
-                                } else {
-                                    route += "([^/]*)";
-                                    matches.insert(item.to_string(), tmp);
-                                }
-                            } else {
-                                route.push('{');
-                                route += item;
-                                route.push('}');
-                            }
-                            continue;
-                        }
-                    }
+        match self.handle {
+            Handler::Sync(ref handle) => {
+                if let Err(err) = handle(context) {
+                    context.fail(err);
                 }
             }
+            Handler::Async(ref handle) => {
+                let taken = context.take();
+                *context = runtime::Handle::current().block_on(handle(taken));
+            }
         }
+    }
 
-        if bracket_count == 0 {
-            if ch == '(' {
-                parenthese_count += 1;
-            } else {
-                if ch == ')' {
-                    parenthese_count -= 1;
-                    if parenthese_count == 0 {
-                        number_matches += 1;
-                    }
-                }
-            }
+    /// Await an asynchronous handle, synchronous handles are called in place.
+    pub(crate) async fn call_async(&self, mut context: Context) -> Context {
+        if !context.next() {
+            return context;
         }
 
-        if bracket_count > 0 {
-            intermediate += 1;
-        } else {
-            if parenthese_count == 0 && prev_ch != '\\' {
-                if ch == '.' || ch == '+' || ch == '|' || ch == '#' {
-                    route = route + "\\";
+        match self.handle {
+            Handler::Sync(ref handle) => {
+                if let Err(err) = handle(&mut context) {
+                    context.fail(err);
                 }
+
+                context
             }
-            route.push(ch);
-            prev_ch = ch;
+            Handler::Async(ref handle) => handle(context).await,
         }
     }
 
-    (route, matches)
-}
-
-fn compile_pattern(pattern: String) -> String {
-    if pattern.contains("(") || pattern.contains("[") {
-        let mut tmp = String::new();
-
-        tmp.push('^');
-        tmp += &pattern;
-        tmp.push('$');
-
-        return tmp;
+    pub(crate) fn execute_after(&self, context: &mut Context) {
+        for after in self.after.iter() {
+            after.execute(context);
+        }
     }
 
-    pattern
-}
-
-#[test]
-fn compile() {
-    let pattern = "{year:[0-9]{4}}/{title:[a-zA-Z\\-]+}";
-
-    let (route, route_paths) = extract_named_params(pattern);
-    assert_eq!(route, "([0-9]{4})/([a-zA-Z\\-]+)");
-    let mut map: HashMap<String, usize> = HashMap::new();
-    map.insert("title".to_string(), 2);
-    map.insert("year".to_string(), 1);
-    assert_eq!(route_paths, map);
+    middleware!(before);
+    middleware!(after);
 }
@@ -0,0 +1,718 @@
+//! Route tree.
+//!
+//! Patterns are split on `/` and inserted segment by segment into a prefix
+//! tree, so a lookup walks the path once instead of trying every route.
+//!
+//! A segment is one of:
+//!
+//! * static, like `user`, looked up in a map and tried first;
+//! * a parameter, like `{id}`, `{id:[0-9]+}` or `{name}.json`, matched
+//!   against the current segment only, tried in registration order;
+//! * a tail, a parameter whose regex may match `/`, like `{path:.*}`, matched
+//!   against the rest of the path, tried last. A tail which may be empty also
+//!   matches the path ending before it.
+//!
+//! If a branch does not lead to a route for the method, the lookup backtracks
+//! and tries the next candidate.
+use std::collections::HashMap;
+
+use hyper::Method;
+use regex::Regex;
+
+/// Route tree.
+///
+/// # Examples
+///
+/// ```
+/// use sincere::app::router::Router;
+/// use sincere::http::Method;
+///
+/// let mut router = Router::new();
+///
+/// router.insert(Method::GET, "/user/{id:[0-9]+}", "user");
+/// router.insert(Method::GET, "/user/me", "me");
+///
+/// let found = router.find(&Method::GET, "/user/12").unwrap();
+/// assert_eq!(*found.value, "user");
+/// assert_eq!(found.params, vec![("id".to_owned(), "12".to_owned())]);
+///
+/// let found = router.find(&Method::GET, "/user/me").unwrap();
+/// assert_eq!(*found.value, "me");
+///
+/// assert!(router.find(&Method::POST, "/user/12").is_none());
+/// ```
+pub struct Router<T> {
+    root: Node<T>,
+}
+
+/// A route found by `Router::find`.
+#[derive(Debug)]
+pub struct Match<'a, T> {
+    /// value inserted with the route
+    pub value: &'a T,
+    /// path parameters, in pattern order
+    pub params: Vec<(String, String)>,
+}
+
+impl<T> Router<T> {
+    /// Create an empty route tree.
+    pub fn new() -> Router<T> {
+        Router { root: Node::new() }
+    }
+
+    /// Insert a route pattern for a method.
+    ///
+    /// If the pattern is already registered for the method, the first value
+    /// is kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pattern can not be compiled.
+    pub fn insert(&mut self, method: Method, pattern: &str, value: T) {
+        self.insert_pattern(method, &Pattern::parse(pattern), value)
+    }
+
+    pub(crate) fn insert_pattern(&mut self, method: Method, pattern: &Pattern, value: T) {
+        let mut node = &mut self.root;
+
+        for segment in pattern.segments.iter() {
+            match segment {
+                Segment::Static(name) => {
+                    node = node.statics.entry(name.clone()).or_insert_with(Node::new);
+                }
+                Segment::Param(matcher) => {
+                    let index = match node.params.iter().position(|(m, _)| m.source == matcher.source) {
+                        Some(index) => index,
+                        None => {
+                            node.params.push((matcher.clone(), Node::new()));
+                            node.params.len() - 1
+                        }
+                    };
+
+                    node = &mut node.params[index].1;
+                }
+                Segment::Tail(matcher) => {
+                    let index = match node.tails.iter().position(|(m, _)| m.source == matcher.source) {
+                        Some(index) => index,
+                        None => {
+                            node.tails.push((matcher.clone(), Endpoint::new()));
+                            node.tails.len() - 1
+                        }
+                    };
+
+                    node.tails[index].1.insert(method, value);
+                    return;
+                }
+            }
+        }
+
+        node.endpoint.insert(method, value);
+    }
+
+    /// Find the route for a method and a request path.
+    ///
+    /// A trailing slash of the path is ignored.
+    pub fn find<'a>(&'a self, method: &Method, path: &str) -> Option<Match<'a, T>> {
+        let mut params = Vec::new();
+        let mut value = None;
+
+        self.root.search(split_path(path), &mut params, &mut |endpoint: &'a Endpoint<T>| {
+            value = endpoint.get(method);
+            value.is_some()
+        });
+
+        value.map(|value| Match { value, params })
+    }
+
+    /// The methods registered for patterns matching a request path, in
+    /// registration order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::app::router::Router;
+    /// use sincere::http::Method;
+    ///
+    /// let mut router = Router::new();
+    ///
+    /// router.insert(Method::GET, "/user/{id}", ());
+    /// router.insert(Method::DELETE, "/user/{id}", ());
+    /// router.insert(Method::PUT, "/user/me", ());
+    ///
+    /// assert_eq!(router.methods("/user/me"), vec![Method::PUT, Method::GET, Method::DELETE]);
+    /// assert_eq!(router.methods("/user/12"), vec![Method::GET, Method::DELETE]);
+    /// assert!(router.methods("/post").is_empty());
+    /// ```
+    pub fn methods(&self, path: &str) -> Vec<Method> {
+        let mut params = Vec::new();
+        let mut methods: Vec<Method> = Vec::new();
+
+        self.root.search(split_path(path), &mut params, &mut |endpoint: &Endpoint<T>| {
+            for (method, _) in endpoint.values.iter() {
+                if !methods.contains(method) {
+                    methods.push(method.clone());
+                }
+            }
+
+            false
+        });
+
+        methods
+    }
+}
+
+impl<T> Default for Router<T> {
+    fn default() -> Router<T> {
+        Router::new()
+    }
+}
+
+struct Node<T> {
+    statics: HashMap<String, Node<T>>,
+    params: Vec<(Matcher, Node<T>)>,
+    tails: Vec<(Matcher, Endpoint<T>)>,
+    endpoint: Endpoint<T>,
+}
+
+impl<T> Node<T> {
+    fn new() -> Node<T> {
+        Node {
+            statics: HashMap::new(),
+            params: Vec::new(),
+            tails: Vec::new(),
+            endpoint: Endpoint::new(),
+        }
+    }
+
+    /// Walk the tree with the rest of the path, `None` once every segment is
+    /// consumed, until `accept` takes an endpoint.
+    fn search<'a, F>(&'a self, rest: Option<&str>, params: &mut Vec<(String, String)>, accept: &mut F) -> bool
+    where
+        F: FnMut(&'a Endpoint<T>) -> bool,
+    {
+        let rest = match rest {
+            Some(rest) => rest,
+            None => return accept(&self.endpoint) || self.search_tails("", params, accept),
+        };
+
+        let (segment, next) = match rest.find('/') {
+            Some(index) => (&rest[..index], Some(&rest[index + 1..])),
+            None => (rest, None),
+        };
+
+        if let Some(child) = self.statics.get(segment) {
+            if child.search(next, params, accept) {
+                return true;
+            }
+        }
+
+        for (matcher, child) in self.params.iter() {
+            let len = params.len();
+
+            if matcher.captures(segment, params) {
+                if child.search(next, params, accept) {
+                    return true;
+                }
+
+                params.truncate(len);
+            }
+        }
+
+        self.search_tails(rest, params, accept)
+    }
+
+    fn search_tails<'a, F>(&'a self, rest: &str, params: &mut Vec<(String, String)>, accept: &mut F) -> bool
+    where
+        F: FnMut(&'a Endpoint<T>) -> bool,
+    {
+        for (matcher, endpoint) in self.tails.iter() {
+            let len = params.len();
+
+            if matcher.captures(rest, params) {
+                if accept(endpoint) {
+                    return true;
+                }
+
+                params.truncate(len);
+            }
+        }
+
+        false
+    }
+}
+
+struct Endpoint<T> {
+    values: Vec<(Method, T)>,
+}
+
+impl<T> Endpoint<T> {
+    fn new() -> Endpoint<T> {
+        Endpoint { values: Vec::new() }
+    }
+
+    fn insert(&mut self, method: Method, value: T) {
+        if self.get(&method).is_none() {
+            self.values.push((method, value));
+        }
+    }
+
+    fn get(&self, method: &Method) -> Option<&T> {
+        self.values.iter().find(|(m, _)| m == method).map(|(_, v)| v)
+    }
+}
+
+/// Compiled route pattern.
+#[derive(Clone, Debug)]
+pub(crate) struct Pattern {
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Static(String),
+    Param(Matcher),
+    Tail(Matcher),
+}
+
+impl Pattern {
+    /// Compile a route pattern.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pattern can not be compiled.
+    pub(crate) fn parse(pattern: &str) -> Pattern {
+        let raw = split_pattern(pattern);
+
+        let mut segments = Vec::new();
+
+        for (index, segment) in raw.iter().enumerate() {
+            if !segment.contains('{') {
+                segments.push(Segment::Static(segment.to_string()));
+            } else if param_regexes(segment).iter().any(|re| may_match_slash(re)) {
+                let rest = raw[index..].join("/");
+                segments.push(Segment::Tail(Matcher::compile(pattern, &rest)));
+                break;
+            } else {
+                segments.push(Segment::Param(Matcher::compile(pattern, segment)));
+            }
+        }
+
+        Pattern { segments }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Matcher {
+    source: String,
+    /// `None` for a bare `{name}`, which matches any segment
+    regex: Option<Regex>,
+    names: Vec<(String, usize)>,
+}
+
+impl Matcher {
+    fn compile(pattern: &str, source: &str) -> Matcher {
+        let (route, route_paths) = extract_named_params(source);
+
+        if route_paths.is_empty() {
+            panic!("Can't complie route path: {:?}", pattern);
+        }
+
+        let mut names: Vec<(String, usize)> = route_paths.into_iter().collect();
+        names.sort_by_key(|&(_, index)| index);
+
+        if route == "([^/]*)" {
+            return Matcher {
+                source: source.to_owned(),
+                regex: None,
+                names,
+            };
+        }
+
+        match Regex::new(&format!("^{}$", route)) {
+            Ok(regex) => Matcher {
+                source: source.to_owned(),
+                regex: Some(regex),
+                names,
+            },
+            Err(err) => panic!("Can't complie route path: {:?}, err: {:?}", pattern, err),
+        }
+    }
+
+    fn captures(&self, text: &str, params: &mut Vec<(String, String)>) -> bool {
+        let regex = match self.regex {
+            Some(ref regex) => regex,
+            None => {
+                params.push((self.names[0].0.clone(), text.to_owned()));
+                return true;
+            }
+        };
+
+        match regex.captures(text) {
+            Some(caps) => {
+                for (name, index) in self.names.iter() {
+                    let value = caps.get(*index).map(|m| m.as_str()).unwrap_or("");
+                    params.push((name.clone(), value.to_owned()));
+                }
+
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Strip the leading slash and a trailing slash, `None` for the root.
+fn split_path(path: &str) -> Option<&str> {
+    let path = path.trim_end_matches('/');
+
+    if path.is_empty() {
+        return None;
+    }
+
+    Some(path.strip_prefix('/').unwrap_or(path))
+}
+
+/// Split a pattern on the slashes outside of braces.
+fn split_pattern(pattern: &str) -> Vec<&str> {
+    let pattern = match split_path(pattern) {
+        Some(pattern) => pattern,
+        None => return Vec::new(),
+    };
+
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (index, ch) in pattern.char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '/' if depth == 0 => {
+                segments.push(&pattern[start..index]);
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+
+    segments.push(&pattern[start..]);
+
+    segments
+}
+
+/// The regexes of the `{name:regex}` parameters in a segment.
+fn param_regexes(segment: &str) -> Vec<&str> {
+    let mut regexes = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (index, ch) in segment.char_indices() {
+        match ch {
+            '{' => {
+                if depth == 0 {
+                    start = index + 1;
+                }
+                depth += 1;
+            }
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    let param = &segment[start..index];
+                    if let Some(colon) = param.find(':') {
+                        regexes.push(&param[colon + 1..]);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    regexes
+}
+
+/// Whether a parameter regex may match a `/`, and so must be matched against
+/// the rest of the path instead of a single segment.
+fn may_match_slash(regex: &str) -> bool {
+    let mut chars = regex.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some('/') | Some('S') | Some('D') | Some('W') => return true,
+                _ => (),
+            },
+            '.' | '/' => return true,
+            '[' => {
+                let negated = chars.peek() == Some(&'^');
+                let mut slash = false;
+
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '\\' => slash |= chars.next() == Some('/'),
+                        '/' => slash = true,
+                        ']' => break,
+                        _ => (),
+                    }
+                }
+
+                if negated != slash {
+                    return true;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    false
+}
+
+fn extract_named_params(pattern: &str) -> (String, HashMap<String, usize>) {
+    let mut parenthese_count = 0;
+    let mut bracket_count = 0;
+    let mut intermediate = 0;
+    let mut marker = 0;
+    let mut number_matches = 0;
+    let mut tmp;
+    let mut found_pattern;
+
+    let mut prev_ch = '\0';
+    let mut variable;
+    let mut regexp;
+    let mut item;
+    let mut route = String::new();
+
+    let mut not_valid = false;
+
+    let mut matches = HashMap::new();
+
+    if !pattern.is_ascii() {
+        panic!("{:?}", "The ruote pattern must be an ascii");
+    }
+
+    for (cursor, ch) in pattern.chars().enumerate() {
+        if parenthese_count == 0 {
+            if ch == '{' {
+                if bracket_count == 0 {
+                    marker = cursor + 1;
+                    intermediate = 0;
+                    not_valid = false;
+                }
+
+                bracket_count += 1;
+            } else {
+                if ch == '}' {
+                    bracket_count -= 1;
+                    if intermediate > 0 {
+                        if bracket_count == 0 {
+                            number_matches += 1;
+                            variable = "";
+                            regexp = "";
+                            item = &pattern[marker..cursor];
+
+                            for (cursor_var, ch) in item.chars().enumerate() {
+                                if ch == '\0' {
+                                    break;
+                                }
+
+                                if cursor_var == 0
+                                    && !((ch >= 'a' && ch <= 'z') || (ch >= 'A' && ch <= 'Z'))
+                                {
+                                    not_valid = true;
+                                    break;
+                                }
+
+                                if (ch >= 'a' && ch <= 'z')
+                                    || (ch >= 'A' && ch <= 'Z')
+                                    || (ch >= '0' && ch <= '9')
+                                    || ch == '-'
+                                    || ch == '_'
+                                    || ch == ':'
+                                {
+                                    if ch == ':' {
+                                        // let (first, last) = item.split_at(cursor_var);
+                                        // variable = first;
+                                        // regexp = &last[1..];
+                                        variable = &item[..cursor_var];
+                                        regexp = &item[cursor_var + 1..];
+                                        break;
+                                    }
+                                } else {
+                                    not_valid = true;
+                                    break;
+                                }
+                            }
+
+                            if !not_valid {
+                                tmp = number_matches;
+                                if !variable.is_empty() && !regexp.is_empty() {
+                                    found_pattern = 0;
+                                    for regexp_ch in regexp.chars() {
+                                        if regexp_ch == '\0' {
+                                            break;
+                                        }
+
+                                        if found_pattern == 0 {
+                                            if regexp_ch == '(' {
+                                                found_pattern = 1;
+                                            }
+                                        } else {
+                                            if regexp_ch == ')' {
+                                                found_pattern = 2;
+                                                break;
+                                            }
+                                        }
+                                    }
+
+                                    if found_pattern != 2 {
+                                        route.push('(');
+                                        route += regexp;
+                                        route.push(')');
+                                    } else {
+                                        route += regexp;
+                                    }
+                                    matches.insert(variable.to_string(), tmp);
+                                } else {
+                                    route += "([^/]*)";
+                                    matches.insert(item.to_string(), tmp);
+                                }
+                            } else {
+                                route.push('{');
+                                route += item;
+                                route.push('}');
+                            }
+                            continue;
+                        }
+                    }
+                }
+            }
+        }
+
+        if bracket_count == 0 {
+            if ch == '(' {
+                parenthese_count += 1;
+            } else {
+                if ch == ')' {
+                    parenthese_count -= 1;
+                    if parenthese_count == 0 {
+                        number_matches += 1;
+                    }
+                }
+            }
+        }
+
+        if bracket_count > 0 {
+            intermediate += 1;
+        } else {
+            if parenthese_count == 0 && prev_ch != '\\' {
+                if ch == '.' || ch == '+' || ch == '|' || ch == '#' {
+                    route = route + "\\";
+                }
+            }
+            route.push(ch);
+            prev_ch = ch;
+        }
+    }
+
+    (route, matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use hyper::Method;
+
+    use super::{extract_named_params, Router};
+
+    #[test]
+    fn compile() {
+        let pattern = "{year:[0-9]{4}}/{title:[a-zA-Z\\-]+}";
+
+        let (route, route_paths) = extract_named_params(pattern);
+        assert_eq!(route, "([0-9]{4})/([a-zA-Z\\-]+)");
+        let mut map: HashMap<String, usize> = HashMap::new();
+        map.insert("title".to_string(), 2);
+        map.insert("year".to_string(), 1);
+        assert_eq!(route_paths, map);
+    }
+
+    fn params(router: &Router<&'static str>, path: &str) -> Option<(&'static str, Vec<(String, String)>)> {
+        router.find(&Method::GET, path).map(|m| (*m.value, m.params))
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn static_before_param() {
+        let mut router = Router::new();
+
+        router.insert(Method::GET, "/", "root");
+        router.insert(Method::GET, "/user/{id}", "user");
+        router.insert(Method::GET, "/user/me", "me");
+        router.insert(Method::GET, "/user/{id}/posts/", "posts");
+
+        assert_eq!(params(&router, "/"), Some(("root", vec![])));
+        assert_eq!(params(&router, "/user/me"), Some(("me", vec![])));
+        assert_eq!(params(&router, "/user/12/"), Some(("user", pairs(&[("id", "12")]))));
+        assert_eq!(params(&router, "/user/12/posts"), Some(("posts", pairs(&[("id", "12")]))));
+        assert_eq!(params(&router, "/user"), None);
+        assert_eq!(params(&router, "/user/12/likes"), None);
+    }
+
+    #[test]
+    fn regex_params() {
+        let mut router = Router::new();
+
+        router.insert(Method::GET, "/blog/{year:[0-9]{4}}/{title:[a-zA-Z\\-]+}", "blog");
+        router.insert(Method::GET, "/file/{name}.{ext:(json|xml)}", "file");
+        router.insert(Method::GET, "/blog/{slug}/edit", "edit");
+
+        assert_eq!(
+            params(&router, "/blog/2020/hello-world"),
+            Some(("blog", pairs(&[("year", "2020"), ("title", "hello-world")])))
+        );
+        assert_eq!(params(&router, "/blog/20/hello"), None);
+        assert_eq!(
+            params(&router, "/file/data.json"),
+            Some(("file", pairs(&[("name", "data"), ("ext", "json")])))
+        );
+        assert_eq!(params(&router, "/file/data.txt"), None);
+        assert_eq!(params(&router, "/blog/20/edit"), Some(("edit", pairs(&[("slug", "20")]))));
+    }
+
+    #[test]
+    fn tails() {
+        let mut router = Router::new();
+
+        router.insert(Method::GET, "/assets/{path:.+}", "assets");
+        router.insert(Method::GET, "/assets/logo.png", "logo");
+        router.insert(Method::GET, "/date/{date:[0-9]{4}/[0-9]{2}}", "date");
+        router.insert(Method::GET, "/files/{path:.*}", "files");
+
+        assert_eq!(params(&router, "/assets/logo.png"), Some(("logo", vec![])));
+        assert_eq!(
+            params(&router, "/assets/css/site.css"),
+            Some(("assets", pairs(&[("path", "css/site.css")])))
+        );
+        assert_eq!(params(&router, "/assets"), None);
+        assert_eq!(params(&router, "/date/2020/01"), Some(("date", pairs(&[("date", "2020/01")]))));
+        assert_eq!(params(&router, "/files/"), Some(("files", pairs(&[("path", "")]))));
+        assert_eq!(params(&router, "/files"), Some(("files", pairs(&[("path", "")]))));
+        assert_eq!(params(&router, "/files/a/b"), Some(("files", pairs(&[("path", "a/b")]))));
+    }
+
+    #[test]
+    fn methods() {
+        let mut router = Router::new();
+
+        router.insert(Method::POST, "/user/new", "create");
+        router.insert(Method::GET, "/user/{id}", "user");
+        router.insert(Method::GET, "/user/{name}", "ignored");
+
+        assert_eq!(*router.find(&Method::POST, "/user/new").unwrap().value, "create");
+        assert_eq!(*router.find(&Method::GET, "/user/new").unwrap().value, "user");
+        assert!(router.find(&Method::PUT, "/user/new").is_none());
+    }
+}
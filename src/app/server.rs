@@ -0,0 +1,358 @@
+//! App server.
+use std::future::{self, Future};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{self, Body, Request};
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+
+use queen_log::color::Print;
+
+use super::App;
+use crate::error::{Error, Result};
+
+/// App server.
+///
+/// The app is frozen behind an `Arc`, so a server can be cloned, run several
+/// times, or served from inside a larger tokio program.
+///
+/// ```no_run
+/// use sincere::App;
+///
+/// let mut app = App::new();
+///
+/// app.get("/", |context| {
+///     context.response.from_text("Hello world!").unwrap();
+/// });
+///
+/// let server = app.into_server();
+///
+/// let public = server.spawn("0.0.0.0:10001").unwrap();
+///
+/// server.run("127.0.0.1:10002").unwrap();
+///
+/// public.shutdown().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct Server {
+    app: Arc<App>,
+}
+
+impl Server {
+    pub(crate) fn new(mut app: App) -> Server {
+        app.build_router();
+
+        Server { app: Arc::new(app) }
+    }
+
+    /// The app shared by this server.
+    #[inline]
+    pub fn app(&self) -> &Arc<App> {
+        &self.app
+    }
+
+    /// Run the server, blocking the current thread.
+    pub fn run(&self, addr: &str) -> Result<()> {
+        self.run_with_shutdown(addr, future::pending())
+    }
+
+    /// Run the server, blocking the current thread until the `signal` future
+    /// completes and in-flight requests are drained.
+    pub fn run_with_shutdown<F>(&self, addr: &str, signal: F) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        let sincere_logo = Print::green(
+        r"
+         __.._..  . __ .___.__ .___
+        (__  | |\ |/  `[__ [__)[__
+        .__)_|_| \|\__.[___|  \[___
+        "
+        );
+
+        println!("{}", sincere_logo);
+        println!(
+            "    {}{}",
+            Print::green("Server running at http://"),
+            Print::green(addr)
+        );
+
+        let mut rt = Runtime::new()?;
+
+        rt.block_on(self.serve(addr, signal))
+    }
+
+    /// Serve on the current tokio runtime until the `signal` future completes.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use sincere::App;
+    /// use sincere::app::shutdown_signal;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut app = App::new();
+    ///
+    ///     app.get("/", |context| {
+    ///         context.response.from_text("Hello world!").unwrap();
+    ///     });
+    ///
+    ///     let server = app.into_server();
+    ///
+    ///     server.serve("0.0.0.0:10001", shutdown_signal()).await.unwrap();
+    /// }
+    /// ```
+    pub async fn serve<F>(&self, addr: &str, signal: F) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        let listener = bind(addr)?;
+
+        self.serve_listener(listener, signal).await
+    }
+
+    /// Start the server on a background thread with its own runtime.
+    ///
+    /// The listener is bound before returning, so binding errors are reported
+    /// here, and `"127.0.0.1:0"` can be used to pick a free port.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let server = App::new().into_server();
+    ///
+    /// let handle = server.spawn("127.0.0.1:0").unwrap();
+    ///
+    /// println!("listening on {}", handle.local_addr());
+    ///
+    /// handle.shutdown().unwrap();
+    /// ```
+    pub fn spawn(&self, addr: &str) -> Result<ServerHandle> {
+        let listener = bind(addr)?;
+        let local_addr = listener.local_addr()?;
+
+        let (tx, rx) = oneshot::channel::<()>();
+
+        let server = self.clone();
+
+        let thread = thread::spawn(move || {
+            let mut rt = Runtime::new()?;
+
+            rt.block_on(server.serve_listener(listener, async {
+                rx.await.ok();
+            }))
+        });
+
+        Ok(ServerHandle {
+            local_addr,
+            shutdown: tx,
+            thread,
+        })
+    }
+
+    async fn serve_listener<F>(&self, listener: TcpListener, signal: F) -> Result<()>
+    where
+        F: Future<Output = ()>,
+    {
+        let app = self.app.clone();
+
+        let service = make_service_fn(move |_| {
+            let app = app.clone();
+
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+                    let app = app.clone();
+
+                    async move {
+                        let (parts, body) = req.into_parts();
+
+                        Ok::<_, hyper::Error>(App::handle(app, parts, body).await)
+                    }
+                }))
+            }
+        });
+
+        hyper::Server::from_tcp(listener)?
+            .serve(service)
+            .with_graceful_shutdown(signal)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Handle of a server started by `Server::spawn`.
+///
+/// Dropping the handle also shuts the server down, without waiting for it.
+pub struct ServerHandle {
+    local_addr: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl ServerHandle {
+    /// The address the server is listening on.
+    #[inline]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Shut the server down gracefully, and wait until in-flight requests
+    /// are drained.
+    pub fn shutdown(self) -> Result<()> {
+        let _ = self.shutdown.send(());
+
+        match self.thread.join() {
+            Ok(ret) => ret,
+            Err(_) => Err(Error::Error("The server thread has panicked".to_owned())),
+        }
+    }
+}
+
+fn bind(addr: &str) -> Result<TcpListener> {
+    let addr: SocketAddr = addr
+        .parse()
+        .map_err(|_| Error::Error(format!("Address is not valid: {:?}", addr)))?;
+
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+
+    Ok(listener)
+}
+
+/// Completes when the process receives `CTRL-C`, or `SIGTERM` on unix.
+///
+/// Meant to be passed to `App::run_with_shutdown`, so that rolling deploys
+/// stop the server without dropping requests.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(_) => {
+                tokio::signal::ctrl_c().await.ok();
+                return;
+            }
+        };
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = terminate.recv() => ()
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use crate::App;
+
+    #[test]
+    fn spawn_and_shutdown() {
+        let mut app = App::new();
+
+        app.get("/", |context| {
+            context.response.from_text("Hello world!").unwrap();
+        });
+
+        let server = app.into_server();
+
+        let first = server.spawn("127.0.0.1:0").unwrap();
+        let second = server.spawn("127.0.0.1:0").unwrap();
+
+        for handle in [&first, &second].iter() {
+            let mut stream = TcpStream::connect(handle.local_addr()).unwrap();
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .unwrap();
+
+            let mut buf = String::new();
+            stream.read_to_string(&mut buf).unwrap();
+
+            assert!(buf.starts_with("HTTP/1.1 200 OK"));
+            assert!(buf.ends_with("Hello world!"));
+        }
+
+        first.shutdown().unwrap();
+        second.shutdown().unwrap();
+    }
+
+    #[test]
+    fn streaming_chunked_body() {
+        let mut app = App::new();
+
+        app.post("/upload", |context| {
+            let mut data = Vec::new();
+            context.request.body_reader().unwrap().read_to_end(&mut data).unwrap();
+
+            context.response.from_data("text/plain", data).unwrap();
+        }).streaming();
+
+        let handle = app.into_server().spawn("127.0.0.1:0").unwrap();
+
+        let mut stream = TcpStream::connect(handle.local_addr()).unwrap();
+        stream
+            .write_all(b"POST /upload HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nTransfer-Encoding: chunked\r\n\r\n")
+            .unwrap();
+
+        for chunk in ["hello", " ", "world"].iter() {
+            write!(stream, "{:x}\r\n{}\r\n", chunk.len(), chunk).unwrap();
+            stream.flush().unwrap();
+        }
+
+        stream.write_all(b"0\r\n\r\n").unwrap();
+
+        let mut buf = String::new();
+        stream.read_to_string(&mut buf).unwrap();
+
+        assert!(buf.starts_with("HTTP/1.1 200 OK"));
+        assert!(buf.ends_with("hello world"));
+
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn streaming_response_body() {
+        use std::io::Cursor;
+
+        let mut app = App::new();
+
+        app.get("/export", |context| {
+            let data = Cursor::new(vec![b'a'; 100 * 1024]);
+            context.response.from_reader("text/plain", data).unwrap();
+        });
+
+        let handle = app.into_server().spawn("127.0.0.1:0").unwrap();
+
+        let mut stream = TcpStream::connect(handle.local_addr()).unwrap();
+        stream
+            .write_all(b"GET /export HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).unwrap();
+
+        let text = String::from_utf8(buf).unwrap();
+        let (head, body) = text.split_at(text.find("\r\n\r\n").unwrap());
+
+        assert!(head.contains("transfer-encoding: chunked"));
+        assert_eq!(body.matches('a').count(), 100 * 1024);
+
+        handle.shutdown().unwrap();
+    }
+}
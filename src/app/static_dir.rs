@@ -0,0 +1,317 @@
+//! Static file serving.
+use std::fs::{self, File, Metadata};
+use std::io::{Seek, SeekFrom, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use chrono::{DateTime, TimeZone, Utc};
+use percent_encoding::percent_decode_str;
+
+use super::context::Context;
+use crate::error::Result;
+use crate::http::header;
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Directory mounted by `App::static_dir`.
+pub(crate) struct StaticDir {
+    root: PathBuf,
+}
+
+impl StaticDir {
+    pub(crate) fn new(root: PathBuf) -> StaticDir {
+        StaticDir { root }
+    }
+
+    pub(crate) fn serve(&self, context: &mut Context) -> Result<()> {
+        let path = context.request.param("path").unwrap_or_default();
+
+        let file = self.resolve(&path).and_then(|path| {
+            let file = File::open(&path).ok()?;
+            let metadata = file.metadata().ok()?;
+
+            Some((path, file, metadata))
+        });
+
+        let (path, file, metadata) = match file {
+            Some(file) => file,
+            None => {
+                not_found(context);
+                return Ok(());
+            }
+        };
+
+        let length = metadata.len();
+        let etag = etag(&metadata);
+        let modified = modified(&metadata);
+
+        context.response
+            .header((header::ACCEPT_RANGES, "bytes"))?
+            .header((header::ETAG, &*etag))?;
+
+        if let Some(ref modified) = modified {
+            context.response.header((header::LAST_MODIFIED, modified.format(HTTP_DATE).to_string()))?;
+        }
+
+        if not_modified(context, &etag, modified) {
+            context.response.status_code(304);
+            return Ok(());
+        }
+
+        let content_type = mime_guess::from_path(&path).first_or_octet_stream();
+
+        let range = match context.request.header("Range") {
+            Some(ref range) if if_range(context, &etag, modified) => parse_range(range, length),
+            _ => Range::Full,
+        };
+
+        match range {
+            Range::Full => {
+                if context.response.from_file(content_type.to_string(), file).is_err() {
+                    context.response.status_code(500);
+                }
+            }
+            Range::Partial(start, end) => {
+                let mut file = file;
+
+                if file.seek(SeekFrom::Start(start)).is_err() {
+                    context.response.status_code(500);
+                    return Ok(());
+                }
+
+                let len = end - start + 1;
+
+                context.response
+                    .status_code(206)
+                    .header((header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, length)))?;
+
+                context.response
+                    .from_reader(content_type.to_string(), file.take(len))?
+                    .header((header::CONTENT_LENGTH, len))?;
+            }
+            Range::Unsatisfiable => {
+                context.response
+                    .status_code(416)
+                    .header((header::CONTENT_RANGE, format!("bytes */{}", length)))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Map the request path to a file in the directory, `None` if it tries to
+    /// leave it. A directory maps to its `index.html`.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let path = percent_decode_str(path).decode_utf8().ok()?;
+
+        let mut resolved = self.root.clone();
+
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => continue,
+                ".." => return None,
+                _ if segment.contains('\\') || segment.contains('\0') => return None,
+                _ if Path::new(segment).has_root() || segment.contains(':') => return None,
+                _ => resolved.push(segment),
+            }
+        }
+
+        // Symbolic links may still point out of the directory.
+        let root = fs::canonicalize(&self.root).ok()?;
+        let mut resolved = fs::canonicalize(resolved).ok()?;
+
+        if !resolved.starts_with(&root) {
+            return None;
+        }
+
+        if resolved.is_dir() {
+            resolved.push("index.html");
+        }
+
+        if resolved.is_file() {
+            Some(resolved)
+        } else {
+            None
+        }
+    }
+}
+
+enum Range {
+    Full,
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Parse a single `bytes` range, other ranges get the full content.
+fn parse_range(range: &str, length: u64) -> Range {
+    let range = match range.trim().strip_prefix("bytes=") {
+        Some(range) if !range.contains(',') => range.trim(),
+        _ => return Range::Full,
+    };
+
+    let (start, end) = match range.find('-') {
+        Some(index) => (range[..index].trim(), range[index + 1..].trim()),
+        None => return Range::Full,
+    };
+
+    let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(length.saturating_sub(1))),
+        (Ok(start), Err(_)) if end.is_empty() => (start, length.saturating_sub(1)),
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return Range::Unsatisfiable;
+            }
+
+            (length.saturating_sub(suffix), length.saturating_sub(1))
+        }
+        _ => return Range::Full,
+    };
+
+    if start >= length {
+        return Range::Unsatisfiable;
+    }
+
+    Range::Partial(start, end)
+}
+
+fn etag(metadata: &Metadata) -> String {
+    let modified = metadata.modified().ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_nanos())
+        .unwrap_or_default();
+
+    format!("\"{:x}-{:x}\"", metadata.len(), modified)
+}
+
+fn modified(metadata: &Metadata) -> Option<DateTime<Utc>> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    Utc.timestamp_opt(modified.as_secs() as i64, 0).single()
+}
+
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(date).ok().map(|date| date.with_timezone(&Utc))
+}
+
+/// `If-None-Match` wins over `If-Modified-Since`.
+fn not_modified(context: &Context, etag: &str, modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(none_match) = context.request.header("If-None-Match") {
+        return none_match.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    }
+
+    match (context.request.header("If-Modified-Since"), modified) {
+        (Some(since), Some(modified)) => parse_date(&since).is_some_and(|since| modified <= since),
+        _ => false,
+    }
+}
+
+/// Whether the `Range` applies, `If-Range` holds a validator of the current file.
+fn if_range(context: &Context, etag: &str, modified: Option<DateTime<Utc>>) -> bool {
+    let validator = match context.request.header("If-Range") {
+        Some(validator) => validator,
+        None => return true,
+    };
+
+    if validator.starts_with('"') {
+        return validator == etag;
+    }
+
+    match (parse_date(&validator), modified) {
+        (Some(date), Some(modified)) => modified == date,
+        _ => false,
+    }
+}
+
+fn not_found(context: &mut Context) {
+    let app = context.app.clone();
+    app.handle_not_found(context);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::App;
+
+    #[test]
+    fn static_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("docs")).unwrap();
+        fs::write(dir.path().join("hello.txt"), "Hello world!").unwrap();
+        fs::write(dir.path().join("docs/index.html"), "<h1>Docs</h1>").unwrap();
+        fs::write(dir.path().join("index.html"), "<h1>Home</h1>").unwrap();
+
+        let mut app = App::new();
+
+        app.static_dir("/assets", dir.path());
+
+        let client = app.test_client();
+
+        let response = client.get("/assets/hello.txt").send();
+        assert_eq!(response.get_status_code(), 200);
+        assert_eq!(response.get_body(), b"Hello world!");
+        assert_eq!(response.get_header("Content-Type").unwrap(), "text/plain");
+        assert_eq!(response.get_header("Content-Length").unwrap(), "12");
+
+        for path in ["/assets/", "/assets"].iter() {
+            let response = client.get(path).send();
+            assert_eq!(response.get_status_code(), 200, "{}", path);
+            assert_eq!(response.get_body(), b"<h1>Home</h1>");
+        }
+
+        let response = client.get("/assets/docs/").send();
+        assert_eq!(response.get_body(), b"<h1>Docs</h1>");
+        assert_eq!(response.get_header("Content-Type").unwrap(), "text/html");
+
+        for path in ["/assets/../Cargo.toml", "/assets/%2e%2e/Cargo.toml", "/assets/docs/..%2f..%2fhello.txt", "/assets/missing"].iter() {
+            assert_eq!(client.get(path).send().get_status_code(), 404, "{}", path);
+        }
+
+        // Conditional requests.
+        let response = client.get("/assets/hello.txt").send();
+        let etag = response.get_header("ETag").unwrap();
+        let modified = response.get_header("Last-Modified").unwrap();
+
+        let response = client.get("/assets/hello.txt").header("If-None-Match", etag).send();
+        assert_eq!(response.get_status_code(), 304);
+        assert!(response.get_body().is_empty());
+
+        let response = client.get("/assets/hello.txt").header("If-Modified-Since", modified).send();
+        assert_eq!(response.get_status_code(), 304);
+
+        let response = client.get("/assets/hello.txt").header("If-None-Match", "\"other\"").send();
+        assert_eq!(response.get_status_code(), 200);
+
+        // Ranges.
+        let response = client.get("/assets/hello.txt").header("Range", "bytes=0-4").send();
+        assert_eq!(response.get_status_code(), 206);
+        assert_eq!(response.get_body(), b"Hello");
+        assert_eq!(response.get_header("Content-Range").unwrap(), "bytes 0-4/12");
+        assert_eq!(response.get_header("Content-Length").unwrap(), "5");
+
+        let response = client.get("/assets/hello.txt").header("Range", "bytes=6-").send();
+        assert_eq!(response.get_body(), b"world!");
+
+        let response = client.get("/assets/hello.txt").header("Range", "bytes=-6").send();
+        assert_eq!(response.get_body(), b"world!");
+
+        let response = client.get("/assets/hello.txt").header("Range", "bytes=20-").send();
+        assert_eq!(response.get_status_code(), 416);
+        assert_eq!(response.get_header("Content-Range").unwrap(), "bytes */12");
+
+        let response = client.get("/assets/hello.txt")
+            .header("Range", "bytes=0-4")
+            .header("If-Range", "\"other\"")
+            .send();
+        assert_eq!(response.get_status_code(), 200);
+        assert_eq!(response.get_body(), b"Hello world!");
+
+        let response = client.head("/assets/hello.txt").send();
+        assert_eq!(response.get_status_code(), 200);
+        assert_eq!(response.get_header("Content-Length").unwrap(), "12");
+        assert!(response.get_body().is_empty());
+    }
+}
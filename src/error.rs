@@ -6,11 +6,15 @@ use std::result;
 use std::string::FromUtf8Error;
 use std::sync::mpsc::TryRecvError;
 
+use http;
 use httparse;
 use hyper;
 use serde_json;
 use url;
 
+use crate::http::BindError;
+use crate::validate::{Rejection, ValidationErrors};
+
 pub type Result<T> = result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -22,10 +26,43 @@ pub enum Error {
     HttpParseError(httparse::Error),
     ParseIntError(ParseIntError),
     HyperError(hyper::Error),
+    HttpError(http::Error),
     UrlParseError(url::ParseError),
+    PayloadTooLarge(usize),
+    /// A request the client got wrong, such as a malformed body.
+    BadRequest(String),
+    BindError(BindError),
+    ValidationError(ValidationErrors),
     Error(String),
 }
 
+impl Error {
+    /// The status code a response failing with this error should have.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::Error;
+    ///
+    /// assert_eq!(Error::PayloadTooLarge(1024).status_code(), 413);
+    /// assert_eq!(Error::BadRequest("Malformed".to_owned()).status_code(), 400);
+    ///
+    /// // json the server fails to read or write is its own fault, bad json
+    /// // in a request is a `BindError`
+    /// let err: Error = serde_json::from_str::<u32>("x").unwrap_err().into();
+    /// assert_eq!(err.status_code(), 500);
+    /// assert_eq!(Error::Error("Oops".to_owned()).status_code(), 500);
+    /// ```
+    pub fn status_code(&self) -> u16 {
+        match *self {
+            Error::BadRequest(_) | Error::BindError(_) => 400,
+            Error::PayloadTooLarge(_) => 413,
+            Error::ValidationError(_) => 422,
+            _ => 500,
+        }
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
         Error::IoError(err)
@@ -62,6 +99,33 @@ impl From<hyper::Error> for Error {
     }
 }
 
+impl From<http::Error> for Error {
+    fn from(err: http::Error) -> Self {
+        Error::HttpError(err)
+    }
+}
+
+impl From<BindError> for Error {
+    fn from(err: BindError) -> Self {
+        Error::BindError(err)
+    }
+}
+
+impl From<ValidationErrors> for Error {
+    fn from(err: ValidationErrors) -> Self {
+        Error::ValidationError(err)
+    }
+}
+
+impl From<Rejection> for Error {
+    fn from(err: Rejection) -> Self {
+        match err {
+            Rejection::Bind(err) => Error::BindError(err),
+            Rejection::Invalid(err) => Error::ValidationError(err),
+        }
+    }
+}
+
 impl From<url::ParseError> for Error {
     fn from(err: url::ParseError) -> Self {
         Error::UrlParseError(err)
@@ -78,7 +142,14 @@ impl fmt::Display for Error {
             Error::HttpParseError(ref inner) => inner.fmt(fmt),
             Error::ParseIntError(ref inner) => inner.fmt(fmt),
             Error::HyperError(ref inner) => inner.fmt(fmt),
+            Error::HttpError(ref inner) => inner.fmt(fmt),
             Error::UrlParseError(ref inner) => inner.fmt(fmt),
+            Error::PayloadTooLarge(limit) => {
+                write!(fmt, "Payload is larger than the limit of {} bytes", limit)
+            }
+            Error::BadRequest(ref inner) => inner.fmt(fmt),
+            Error::BindError(ref inner) => inner.fmt(fmt),
+            Error::ValidationError(ref inner) => inner.fmt(fmt),
             Error::Error(ref inner) => inner.fmt(fmt),
         }
     }
@@ -94,7 +165,12 @@ impl error::Error for Error {
             Error::HttpParseError(ref err) => err.description(),
             Error::ParseIntError(ref err) => err.description(),
             Error::HyperError(ref err) => err.description(),
+            Error::HttpError(_) => "Invalid http value",
             Error::UrlParseError(ref err) => err.description(),
+            Error::PayloadTooLarge(_) => "Payload too large",
+            Error::BadRequest(ref err) => err,
+            Error::BindError(_) => "Can't bind the request",
+            Error::ValidationError(_) => "Invalid request",
             Error::Error(ref err) => err,
         }
     }
@@ -108,7 +184,12 @@ impl error::Error for Error {
             Error::HttpParseError(ref err) => Some(err),
             Error::ParseIntError(ref err) => Some(err),
             Error::HyperError(ref err) => Some(err),
+            Error::HttpError(ref err) => Some(err),
             Error::UrlParseError(ref err) => Some(err),
+            Error::PayloadTooLarge(_) => None,
+            Error::BadRequest(_) => None,
+            Error::BindError(ref err) => Some(err),
+            Error::ValidationError(ref err) => Some(err),
             Error::Error(_) => None,
         }
     }
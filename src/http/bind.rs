@@ -0,0 +1,360 @@
+//! Typed request binders.
+use std::error;
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+
+use super::request::Request;
+use crate::util::url;
+use crate::validate::{Rejection, Validate};
+
+/// Part of the request a value is bound from.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Location {
+    Params,
+    Query,
+    Body,
+}
+
+/// Error of the `Request::bind_*` methods, meant to be sent back as a
+/// 400 Bad Request.
+///
+/// # Examples
+///
+/// ```
+/// use serde::Deserialize;
+///
+/// use sincere::App;
+///
+/// #[derive(Deserialize)]
+/// struct Page {
+///     page: u32,
+/// }
+///
+/// let mut app = App::new();
+///
+/// app.get("/", |context| {
+///     match context.request.bind_query::<Page>() {
+///         Ok(page) => {
+///             context.response.from_text(page.page.to_string()).unwrap();
+///         }
+///         Err(err) => {
+///             context.response.status_code(err.status_code()).from_json(&err).unwrap();
+///         }
+///     }
+/// });
+///
+/// let client = app.test_client();
+///
+/// assert_eq!(client.get("/?page=2").send().get_body(), b"2");
+///
+/// let response = client.get("/?page=two").send();
+///
+/// assert_eq!(response.get_status_code(), 400);
+/// assert_eq!(response.get_body(), br#"{"location":"query","message":"invalid digit found in string"}"#);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BindError {
+    /// Where the value was taken from.
+    pub location: Location,
+    /// What went wrong.
+    pub message: String,
+}
+
+impl BindError {
+    pub fn new<M: fmt::Display>(location: Location, message: M) -> BindError {
+        BindError {
+            location,
+            message: message.to_string(),
+        }
+    }
+
+    /// Always 400 Bad Request.
+    #[inline]
+    pub fn status_code(&self) -> u16 {
+        400
+    }
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Can't bind {:?}: {}", self.location, self.message)
+    }
+}
+
+impl error::Error for BindError {}
+
+impl Request {
+    /// Deserialize the query string.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    ///
+    /// use sincere::App;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Search {
+    ///     q: String,
+    ///     page: Option<u32>,
+    /// }
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/search", |context| {
+    ///     let search: Search = context.request.bind_query().unwrap();
+    ///
+    ///     let text = format!("{} {}", search.q, search.page.unwrap_or(1));
+    ///     context.response.from_text(text).unwrap();
+    /// });
+    ///
+    /// let response = app.test_client().get("/search?q=rust").send();
+    ///
+    /// assert_eq!(response.get_body(), b"rust 1");
+    /// ```
+    pub fn bind_query<D: DeserializeOwned>(&self) -> Result<D, BindError> {
+        url::from_pairs(self.querys()).map_err(|err| BindError::new(Location::Query, err))
+    }
+
+    /// Deserialize the fields of an urlencoded or multipart form body.
+    pub fn bind_form<D: DeserializeOwned>(&self) -> Result<D, BindError> {
+        url::from_pairs(self.posts()).map_err(|err| BindError::new(Location::Body, err))
+    }
+
+    /// Deserialize the route parameters.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    ///
+    /// use sincere::App;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Path {
+    ///     id: u64,
+    /// }
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/user/{id}", |context| {
+    ///     let path: Path = context.request.bind_params().unwrap();
+    ///     context.response.from_text(path.id.to_string()).unwrap();
+    /// });
+    ///
+    /// assert_eq!(app.test_client().get("/user/12").send().get_body(), b"12");
+    /// ```
+    pub fn bind_params<D: DeserializeOwned>(&self) -> Result<D, BindError> {
+        let params: Vec<(&str, &str)> = self
+            .get_params()
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+
+        url::from_pairs(&params).map_err(|err| BindError::new(Location::Params, err))
+    }
+
+    /// Deserialize the body, as json or form fields depending on its
+    /// `Content-Type`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_json::json;
+    ///
+    /// use sincere::App;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.post("/user", |context| {
+    ///     let user: User = context.request.bind().unwrap();
+    ///     context.response.from_text(user.name).unwrap();
+    /// });
+    ///
+    /// let client = app.test_client();
+    ///
+    /// let response = client.post("/user").json(&json!({"name": "json"})).send();
+    /// assert_eq!(response.get_body(), b"json");
+    ///
+    /// let response = client.post("/user").form(&[("name", "form")]).send();
+    /// assert_eq!(response.get_body(), b"form");
+    /// ```
+    pub fn bind<D: DeserializeOwned>(&self) -> Result<D, BindError> {
+        let content_type = match self.content_type() {
+            Some(content_type) => content_type,
+            None => return Err(BindError::new(Location::Body, "Missing content type")),
+        };
+
+        let json = content_type.subtype() == mime::JSON || content_type.suffix() == Some(mime::JSON);
+
+        if content_type.type_() == mime::APPLICATION && json {
+            serde_json::from_slice(self.body()).map_err(|err| BindError::new(Location::Body, err))
+        } else if content_type.essence_str() == mime::APPLICATION_WWW_FORM_URLENCODED.essence_str()
+            || content_type.essence_str() == mime::MULTIPART_FORM_DATA.essence_str()
+        {
+            self.bind_form()
+        } else {
+            Err(BindError::new(
+                Location::Body,
+                format!("Unsupported content type: {}", content_type.essence_str()),
+            ))
+        }
+    }
+
+    /// Deserialize and validate the query string.
+    pub fn valid_query<D: DeserializeOwned + Validate>(&self) -> Result<D, Rejection> {
+        validated(self.bind_query(), Location::Query)
+    }
+
+    /// Deserialize and validate the fields of a form body.
+    pub fn valid_form<D: DeserializeOwned + Validate>(&self) -> Result<D, Rejection> {
+        validated(self.bind_form(), Location::Body)
+    }
+
+    /// Deserialize and validate the route parameters.
+    pub fn valid_params<D: DeserializeOwned + Validate>(&self) -> Result<D, Rejection> {
+        validated(self.bind_params(), Location::Params)
+    }
+
+    /// Deserialize and validate a json body, whatever its `Content-Type`.
+    pub fn valid_json<D: DeserializeOwned + Validate>(&self) -> Result<D, Rejection> {
+        let value = serde_json::from_slice(self.body()).map_err(|err| BindError::new(Location::Body, err));
+
+        validated(value, Location::Body)
+    }
+
+    /// Deserialize and validate the body, as `bind` does.
+    pub fn valid<D: DeserializeOwned + Validate>(&self) -> Result<D, Rejection> {
+        validated(self.bind(), Location::Body)
+    }
+}
+
+fn validated<D: Validate>(value: Result<D, BindError>, location: Location) -> Result<D, Rejection> {
+    let value = value?;
+
+    if let Err(mut errors) = value.validate() {
+        errors.location = Some(location);
+        return Err(Rejection::Invalid(errors));
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::{BindError, Location};
+    use crate::validate::{Validate, Validator};
+    use crate::App;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Form {
+        name: String,
+        age: u8,
+        admin: Option<bool>,
+    }
+
+    #[test]
+    fn bind() {
+        let mut app = App::new();
+
+        app.post("/{id}", |context| {
+            #[derive(Deserialize)]
+            struct Params {
+                id: u32,
+            }
+
+            let params: Params = context.request.bind_params().unwrap();
+            assert_eq!(params.id, 7);
+
+            match context.request.bind::<Form>() {
+                Ok(form) => context.response.from_text(format!("{:?}", form)).unwrap(),
+                Err(err) => context.response.status_code(err.status_code()).from_json(&err).unwrap(),
+            };
+        });
+
+        let client = app.test_client();
+
+        let response = client.post("/7").form(&[("name", "a"), ("age", "3"), ("admin", "true")]).send();
+        assert_eq!(response.get_body(), br#"Form { name: "a", age: 3, admin: Some(true) }"#);
+
+        let response = client
+            .post("/7")
+            .header("Content-Type", "multipart/form-data; boundary=b")
+            .body("--b\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\nb\r\n--b\r\nContent-Disposition: form-data; name=\"age\"\r\n\r\n4\r\n--b--\r\n")
+            .send();
+        assert_eq!(response.get_body(), br#"Form { name: "b", age: 4, admin: None }"#);
+
+        let response = client
+            .post("/7")
+            .header("Content-Type", "application/vnd.api+json")
+            .body(r#"{"name": "c", "age": 5}"#)
+            .send();
+        assert_eq!(response.get_body(), br#"Form { name: "c", age: 5, admin: None }"#);
+
+        let response = client.post("/7").form(&[("name", "a"), ("age", "300")]).send();
+        assert_eq!(response.get_status_code(), 400);
+
+        let response = client.post("/7").form(&[("name", "a")]).send();
+        assert_eq!(response.get_body(), br#"{"location":"body","message":"missing field `age`"}"#);
+
+        let response = client.post("/7").header("Content-Type", "text/plain").body("a").send();
+        assert_eq!(response.get_body(), br#"{"location":"body","message":"Unsupported content type: text/plain"}"#);
+
+        assert_eq!(
+            client.post("/7").send().get_body(),
+            &*serde_json::to_vec(&BindError::new(Location::Body, "Missing content type")).unwrap()
+        );
+    }
+
+    #[test]
+    fn valid() {
+        #[derive(Deserialize)]
+        struct Query {
+            page: u32,
+            tags: Vec<String>,
+        }
+
+        impl Validate for Query {
+            fn rules(&self, validator: &mut Validator) {
+                validator.field("page", &self.page).range(1, 10);
+                validator.field("tags", &self.tags).max_length(2);
+            }
+        }
+
+        let mut app = App::new();
+
+        app.get("/", |context| {
+            match context.request.valid_query::<Query>() {
+                Ok(query) => context.response.from_text(query.page.to_string()).unwrap(),
+                Err(err) => context.response.status_code(err.status_code()).from_json(&err).unwrap(),
+            };
+        });
+
+        let client = app.test_client();
+
+        assert_eq!(client.get("/?page=2&tags=a").send().get_body(), b"2");
+
+        let response = client.get("/?page=0&tags=a&tags=b&tags=c").send();
+        assert_eq!(response.get_status_code(), 422);
+        assert_eq!(
+            response.get_body(),
+            &br#"{"location":"query","errors":[{"field":"page","code":"range","message":"must be between 1 and 10"},{"field":"tags","code":"length","message":"length must be at most 2"}]}"#[..]
+        );
+
+        let response = client.get("/?page=a").send();
+        assert_eq!(response.get_status_code(), 400);
+        assert_eq!(response.get_body(), br#"{"location":"query","message":"invalid digit found in string"}"#);
+    }
+}
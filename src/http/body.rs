@@ -0,0 +1,163 @@
+//! Streaming request body.
+use std::future;
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use hyper::body::{Bytes, HttpBody};
+
+use crate::error::{Error, Result};
+use crate::util::executor::block_on;
+
+/// Synchronous reader over a streaming request body, for blocking handles.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Read;
+///
+/// use sincere::App;
+///
+/// let mut app = App::new();
+///
+/// app.post("/upload", |context| {
+///     let mut reader = context.request.body_reader().unwrap();
+///
+///     let mut size = 0;
+///     let mut buf = [0; 4096];
+///
+///     loop {
+///         let len = reader.read(&mut buf).unwrap();
+///         if len == 0 {
+///             break;
+///         }
+///         size += len;
+///     }
+///
+///     context.response.from_text(size.to_string()).unwrap();
+/// }).streaming();
+///
+/// let response = app.test_client().post("/upload").body(vec![0; 10000]).send();
+///
+/// assert_eq!(response.get_body(), b"10000");
+/// ```
+#[derive(Debug)]
+pub struct BodyReader {
+    stream: BodyStream,
+    chunk: Bytes,
+}
+
+impl BodyReader {
+    pub(crate) fn new(stream: BodyStream) -> BodyReader {
+        BodyReader {
+            stream,
+            chunk: Bytes::new(),
+        }
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match block_on(self.stream.chunk()) {
+                Some(Ok(chunk)) => self.chunk = chunk,
+                Some(Err(err)) => return Err(io::Error::other(err)),
+                None => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk.split_to(len));
+
+        Ok(len)
+    }
+}
+
+/// Asynchronous stream of the chunks of a streaming request body.
+///
+/// Once more than the body size limit of the route is read, the stream
+/// yields `Error::PayloadTooLarge` and ends.
+///
+/// # Examples
+///
+/// ```
+/// use sincere::App;
+///
+/// let mut app = App::new();
+///
+/// app.post_async("/upload", |mut context| async move {
+///     let mut stream = context.request.body_stream().unwrap();
+///
+///     let mut size = 0;
+///
+///     while let Some(chunk) = stream.chunk().await {
+///         size += chunk.unwrap().len();
+///     }
+///
+///     context.response.from_text(size.to_string()).unwrap();
+///     context
+/// }).streaming();
+///
+/// let response = app.test_client().post("/upload").body(vec![0; 10000]).send();
+///
+/// assert_eq!(response.get_body(), b"10000");
+/// ```
+#[derive(Debug)]
+pub struct BodyStream {
+    body: hyper::Body,
+    limit: Option<usize>,
+    read: usize,
+}
+
+impl BodyStream {
+    pub(crate) fn new(body: hyper::Body, limit: Option<usize>) -> BodyStream {
+        BodyStream {
+            body,
+            limit,
+            read: 0,
+        }
+    }
+
+    /// The next chunk of the body, `None` at the end.
+    pub async fn chunk(&mut self) -> Option<Result<Bytes>> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Read the rest of the body into memory.
+    pub async fn bytes(mut self) -> Result<Bytes> {
+        let mut buf = Vec::new();
+
+        while let Some(chunk) = self.chunk().await {
+            buf.extend_from_slice(&chunk?);
+        }
+
+        Ok(Bytes::from(buf))
+    }
+}
+
+impl Stream for BodyStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(limit) = self.limit {
+            if self.read > limit {
+                return Poll::Ready(None);
+            }
+        }
+
+        let chunk = match Pin::new(&mut self.body).poll_data(cx) {
+            Poll::Ready(Some(Ok(chunk))) => chunk,
+            Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err.into()))),
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        self.read += chunk.len();
+
+        match self.limit {
+            Some(limit) if self.read > limit => Poll::Ready(Some(Err(Error::PayloadTooLarge(limit)))),
+            _ => Poll::Ready(Some(Ok(chunk))),
+        }
+    }
+}
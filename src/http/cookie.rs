@@ -0,0 +1,504 @@
+//! Cookies, plain, signed or encrypted.
+//!
+//! Names and values are percent-encoded when they hold characters a cookie
+//! can't carry, and decoded when read back.
+//!
+//! Signed cookies are readable by the client but can't be changed without
+//! the app's secret, private cookies are encrypted with AES-256-GCM. Both
+//! are bound to their name, so their value can't be moved to another
+//! cookie.
+use std::fmt;
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use rand::RngCore;
+use sha2::Sha256;
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Characters percent-encoded in values, all but RFC 6265 cookie-octets.
+const VALUE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'%')
+    .add(b',')
+    .add(b';')
+    .add(b'\\');
+
+/// Characters percent-encoded in names, all but RFC 7230 tchars.
+const NAME: &AsciiSet = &VALUE
+    .add(b'(')
+    .add(b')')
+    .add(b'/')
+    .add(b':')
+    .add(b'<')
+    .add(b'=')
+    .add(b'>')
+    .add(b'?')
+    .add(b'@')
+    .add(b'[')
+    .add(b']')
+    .add(b'{')
+    .add(b'}');
+
+/// Characters percent-encoded in the path and domain, so that they can't
+/// add attributes.
+const ATTRIBUTE: &AsciiSet = &CONTROLS.add(b';');
+
+/// Length of a base64 encoded HMAC-SHA256 signature.
+const SIGNATURE_LEN: usize = 43;
+
+const NONCE_LEN: usize = 12;
+
+/// The `SameSite` attribute.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+/// A cookie, as read from a request or set on a response.
+///
+/// # Examples
+///
+/// ```
+/// use sincere::http::cookie::{Cookie, SameSite};
+///
+/// let cookie = Cookie::new("theme", "dark")
+///     .path("/")
+///     .max_age(chrono::Duration::days(7))
+///     .http_only()
+///     .same_site(SameSite::Lax);
+///
+/// assert_eq!(cookie.to_string(), "theme=dark; Path=/; Max-Age=604800; HttpOnly; SameSite=Lax");
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<DateTime<Utc>>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    pub fn new<N, V>(name: N, value: V) -> Cookie
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    pub fn path<S: Into<String>>(mut self, path: S) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn domain<S: Into<String>>(mut self, domain: S) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn expires(mut self, expires: DateTime<Utc>) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    pub fn http_only(mut self) -> Self {
+        self.http_only = true;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn get_domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    pub fn get_max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    pub fn get_expires(&self) -> Option<DateTime<Utc>> {
+        self.expires
+    }
+
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    pub fn is_http_only(&self) -> bool {
+        self.http_only
+    }
+
+    pub fn get_same_site(&self) -> Option<SameSite> {
+        self.same_site
+    }
+
+    /// Whether a cookie set on a response replaces this one in the browser.
+    pub(crate) fn same_as(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.path == other.path && self.domain == other.domain
+    }
+
+    pub(crate) fn into_signed(mut self, key: &Key) -> Cookie {
+        self.value = key.sign(&self.name, &self.value);
+        self
+    }
+
+    pub(crate) fn into_private(mut self, key: &Key) -> Cookie {
+        self.value = key.encrypt(&self.name, &self.value);
+        self
+    }
+
+    /// Make the cookie remove itself from the browser.
+    pub(crate) fn into_removal(mut self) -> Cookie {
+        self.value = String::new();
+        self.max_age = Some(Duration::zero());
+        self.expires = Some(DateTime::<Utc>::from_timestamp(0, 0).unwrap_or_default());
+        self
+    }
+
+    /// Parse the `name=value` pairs of a `Cookie` request header, pairs
+    /// without `=` are skipped.
+    pub(crate) fn parse_header(header: &str) -> Vec<Cookie> {
+        header
+            .split(';')
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                let name = name.trim();
+                let value = value.trim();
+
+                if name.is_empty() {
+                    return None;
+                }
+
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+
+                Some(Cookie::new(decode(name), decode(value)))
+            })
+            .collect()
+    }
+}
+
+fn decode(value: &str) -> String {
+    match percent_decode_str(value).decode_utf8() {
+        Ok(decoded) => decoded.into_owned(),
+        Err(_) => value.to_owned(),
+    }
+}
+
+/// Format as a `Set-Cookie` header value.
+impl fmt::Display for Cookie {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{}={}",
+            utf8_percent_encode(&self.name, NAME),
+            utf8_percent_encode(&self.value, VALUE)
+        )?;
+
+        if let Some(ref path) = self.path {
+            write!(fmt, "; Path={}", utf8_percent_encode(path, ATTRIBUTE))?;
+        }
+
+        if let Some(ref domain) = self.domain {
+            write!(fmt, "; Domain={}", utf8_percent_encode(domain, ATTRIBUTE))?;
+        }
+
+        if let Some(max_age) = self.max_age {
+            write!(fmt, "; Max-Age={}", max_age.num_seconds())?;
+        }
+
+        if let Some(expires) = self.expires {
+            write!(fmt, "; Expires={}", expires.format(HTTP_DATE))?;
+        }
+
+        if self.secure {
+            write!(fmt, "; Secure")?;
+        }
+
+        if self.http_only {
+            write!(fmt, "; HttpOnly")?;
+        }
+
+        if let Some(same_site) = self.same_site {
+            write!(fmt, "; SameSite={:?}", same_site)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Keys of the signed and private cookies, derived from the app secret.
+///
+/// # Examples
+///
+/// ```
+/// use sincere::App;
+/// use sincere::http::cookie::Cookie;
+///
+/// let mut app = App::new();
+///
+/// app.secret_key(b"an app secret of at least 32 bytes, kept out of the repository");
+///
+/// app.get("/login", |context| -> sincere::error::Result<()> {
+///     context.response.set_signed_cookie(Cookie::new("user", "12"))?;
+///     context.response.set_private_cookie(Cookie::new("token", "s3cr3t"))?;
+///     Ok(())
+/// });
+///
+/// app.get("/", |context| {
+///     let user = context.request.signed_cookie("user").unwrap_or_default();
+///     let token = context.request.private_cookie("token").unwrap_or_default();
+///
+///     format!("{} {}", user, token)
+/// });
+///
+/// let client = app.test_client();
+///
+/// let response = client.get("/login").send();
+///
+/// let cookies: Vec<String> = response
+///     .get_cookies()
+///     .iter()
+///     .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+///     .collect();
+///
+/// let response = client.get("/").header("Cookie", &cookies.join("; ")).send();
+/// assert_eq!(response.get_body(), b"12 s3cr3t");
+///
+/// let response = client.get("/").header("Cookie", "user=12; token=s3cr3t").send();
+/// assert_eq!(response.get_body(), b" ");
+/// ```
+#[derive(Clone)]
+pub struct Key {
+    signing: [u8; 32],
+    encryption: [u8; 32],
+}
+
+impl Key {
+    /// Derive the keys from a secret.
+    ///
+    /// # Panics
+    ///
+    /// If the secret is shorter than 32 bytes.
+    pub fn derive(secret: &[u8]) -> Key {
+        assert!(secret.len() >= 32, "The secret key must be at least 32 bytes long");
+
+        Key {
+            signing: hmac(secret, b"sincere cookie signing"),
+            encryption: hmac(secret, b"sincere cookie encryption"),
+        }
+    }
+
+    /// Random keys, cookies won't survive a restart.
+    pub fn generate() -> Key {
+        let mut secret = [0; 64];
+        rand::thread_rng().fill_bytes(&mut secret);
+
+        Key::derive(&secret)
+    }
+
+    /// Prefix the value with a signature of the name and value.
+    pub(crate) fn sign(&self, name: &str, value: &str) -> String {
+        let signature = self.mac(name, value).finalize().into_bytes();
+
+        URL_SAFE_NO_PAD.encode(signature) + value
+    }
+
+    /// The value of a signed cookie, if its signature is valid.
+    pub(crate) fn verify(&self, name: &str, signed: &str) -> Option<String> {
+        if !signed.is_char_boundary(SIGNATURE_LEN) {
+            return None;
+        }
+
+        let (signature, value) = signed.split_at(SIGNATURE_LEN);
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+        self.mac(name, value).verify_slice(&signature).ok()?;
+
+        Some(value.to_owned())
+    }
+
+    /// Encrypt the value, with the name as associated data.
+    pub(crate) fn encrypt(&self, name: &str, value: &str) -> String {
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let payload = Payload {
+            msg: value.as_bytes(),
+            aad: name.as_bytes(),
+        };
+
+        let mut data = nonce.to_vec();
+
+        data.extend(
+            self.cipher()
+                .encrypt(Nonce::from_slice(&nonce), payload)
+                .expect("Encrypting a cookie can't fail"),
+        );
+
+        URL_SAFE_NO_PAD.encode(data)
+    }
+
+    /// The value of a private cookie, if it decrypts.
+    pub(crate) fn decrypt(&self, name: &str, encrypted: &str) -> Option<String> {
+        let data = URL_SAFE_NO_PAD.decode(encrypted).ok()?;
+
+        if data.len() < NONCE_LEN {
+            return None;
+        }
+
+        let (nonce, msg) = data.split_at(NONCE_LEN);
+
+        let payload = Payload {
+            msg,
+            aad: name.as_bytes(),
+        };
+
+        let value = self.cipher().decrypt(Nonce::from_slice(nonce), payload).ok()?;
+
+        String::from_utf8(value).ok()
+    }
+
+    fn mac(&self, name: &str, value: &str) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.signing).expect("HMAC takes keys of any size");
+        mac.update(name.as_bytes());
+        mac.update(b"=");
+        mac.update(value.as_bytes());
+        mac
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new((&self.encryption).into())
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("Key")
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::{Cookie, Key, SameSite};
+
+    #[test]
+    fn format_and_parse() {
+        let cookie = Cookie::new("a b", "x;y \"z\" 你")
+            .domain("example.com")
+            .path("/app")
+            .expires(Utc.with_ymd_and_hms(2020, 1, 2, 3, 4, 5).unwrap())
+            .max_age(Duration::hours(1))
+            .secure()
+            .same_site(SameSite::Strict);
+
+        let header = cookie.to_string();
+
+        assert_eq!(
+            header,
+            "a%20b=x%3By%20%22z%22%20%E4%BD%A0; Path=/app; Domain=example.com; Max-Age=3600; \
+             Expires=Thu, 02 Jan 2020 03:04:05 GMT; Secure; SameSite=Strict"
+        );
+
+        let pair = header.split(';').next().unwrap();
+        let parsed = Cookie::parse_header(&format!("{}; theme=\"dark\";flag; =empty;  lang = en ", pair));
+
+        let pairs: Vec<(&str, &str)> = parsed.iter().map(|cookie| (cookie.name(), cookie.value())).collect();
+
+        assert_eq!(
+            pairs,
+            vec![("a b", "x;y \"z\" 你"), ("theme", "dark"), ("lang", "en")]
+        );
+
+        let removal = Cookie::new("a", "b").path("/").into_removal();
+        assert_eq!(removal.to_string(), "a=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn signed_and_private() {
+        let key = Key::derive(&[7; 32]);
+        let other = Key::generate();
+
+        let signed = key.sign("user", "12");
+        assert!(signed.ends_with("12"));
+        assert_eq!(key.verify("user", &signed), Some("12".to_owned()));
+        assert_eq!(key.verify("admin", &signed), None);
+        assert_eq!(other.verify("user", &signed), None);
+        assert_eq!(key.verify("user", &signed.replace("12", "13")), None);
+        assert_eq!(key.verify("user", "12"), None);
+
+        let encrypted = key.encrypt("token", "s3cr3t");
+        assert!(!encrypted.contains("s3cr3t"));
+        assert_ne!(encrypted, key.encrypt("token", "s3cr3t"));
+        assert_eq!(key.decrypt("token", &encrypted), Some("s3cr3t".to_owned()));
+        assert_eq!(key.decrypt("other", &encrypted), None);
+        assert_eq!(other.decrypt("token", &encrypted), None);
+        assert_eq!(key.decrypt("token", "bm9uY2U"), None);
+        assert_eq!(key.decrypt("token", "!"), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn short_secret() {
+        Key::derive(b"short");
+    }
+}
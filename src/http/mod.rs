@@ -1,9 +1,15 @@
+pub use self::bind::{BindError, Location};
+pub use self::body::{BodyReader, BodyStream};
 pub use self::request::Request;
-pub use self::response::Response;
+pub(crate) use self::request::Payload;
+pub use self::response::{IntoResponse, Response};
 pub use hyper::{header, HeaderMap, Method};
 pub use hyper::body::Bytes;
 
+pub mod cookie;
 pub mod plus;
+mod bind;
+mod body;
 mod request;
 mod response;
 mod status_code;
@@ -0,0 +1,281 @@
+//! Content-Disposition header of multipart parts.
+use percent_encoding::percent_decode;
+
+/// Parsed `Content-Disposition` header, as sent for each part of
+/// multipart/form-data.
+///
+/// Parameter values may be tokens or quoted strings with backslash escapes,
+/// and `filename*` is decoded as an RFC 5987 extended value. It takes
+/// precedence over `filename`.
+///
+/// # Examples
+///
+/// ```
+/// use sincere::http::plus::server::ContentDisposition;
+///
+/// let value = r#"form-data; name="file"; filename="na\"me.txt"; filename*=UTF-8''%E4%BD%A0%E5%A5%BD.txt"#;
+///
+/// let disposition = ContentDisposition::parse(value).unwrap();
+///
+/// assert_eq!(disposition.kind, "form-data");
+/// assert_eq!(disposition.name(), Some("file"));
+/// assert_eq!(disposition.filename(), Some("你好.txt"));
+/// assert_eq!(disposition.param("filename"), Some("na\"me.txt"));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContentDisposition {
+    /// Disposition type, lowercased, `form-data` for form parts.
+    pub kind: String,
+    /// Parameters with lowercased names, in order, extended values decoded.
+    pub params: Vec<(String, String)>,
+}
+
+impl ContentDisposition {
+    /// Parse a header value, `None` if it has no disposition type.
+    pub fn parse(value: &str) -> Option<ContentDisposition> {
+        let mut parser = Parser {
+            input: value.as_bytes(),
+            pos: 0,
+        };
+
+        let kind = parser.token()?.to_ascii_lowercase();
+
+        let mut params = Vec::new();
+
+        loop {
+            parser.skip_whitespace();
+
+            if !parser.eat(b';') {
+                break;
+            }
+
+            parser.skip_whitespace();
+
+            let name = match parser.token() {
+                Some(name) => name.to_ascii_lowercase(),
+                // Tolerate trailing or doubled semicolons.
+                None => continue,
+            };
+
+            parser.skip_whitespace();
+
+            if !parser.eat(b'=') {
+                params.push((name, String::new()));
+                continue;
+            }
+
+            parser.skip_whitespace();
+
+            let value = if parser.peek() == Some(b'"') {
+                parser.quoted()
+            } else {
+                parser.until_semicolon()
+            };
+
+            if name.ends_with('*') {
+                if let Some(value) = decode_extended(&value) {
+                    params.push((name, value));
+                }
+            } else {
+                params.push((name, value));
+            }
+        }
+
+        Some(ContentDisposition { kind, params })
+    }
+
+    /// A parameter value, the name is case-insensitive.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The `name` of the form field.
+    pub fn name(&self) -> Option<&str> {
+        self.param("name*").or_else(|| self.param("name"))
+    }
+
+    /// The file name, `filename*` if present.
+    pub fn filename(&self) -> Option<&str> {
+        self.param("filename*").or_else(|| self.param("filename"))
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).cloned()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// An RFC 7230 token.
+    fn token(&mut self) -> Option<String> {
+        let start = self.pos;
+
+        while let Some(byte) = self.peek() {
+            if byte <= b' ' || byte >= 0x7f || b"()<>@,;:\\\"/[]?={}".contains(&byte) {
+                break;
+            }
+
+            self.pos += 1;
+        }
+
+        if self.pos == start {
+            return None;
+        }
+
+        Some(String::from_utf8_lossy(&self.input[start..self.pos]).into_owned())
+    }
+
+    /// A quoted string, browsers send non-ASCII file names as raw UTF-8.
+    ///
+    /// Only `\"` and `\\` are unescaped, so that Windows paths sent by old
+    /// browsers keep their backslashes.
+    fn quoted(&mut self) -> String {
+        self.pos += 1;
+
+        let mut value = Vec::new();
+
+        while let Some(byte) = self.peek() {
+            self.pos += 1;
+
+            match byte {
+                b'"' => break,
+                b'\\' => match self.peek() {
+                    Some(next @ b'"') | Some(next @ b'\\') => {
+                        value.push(next);
+                        self.pos += 1;
+                    }
+                    _ => value.push(byte),
+                },
+                _ => value.push(byte),
+            }
+        }
+
+        String::from_utf8_lossy(&value).into_owned()
+    }
+
+    /// An unquoted value, which some clients send with spaces in it.
+    fn until_semicolon(&mut self) -> String {
+        let start = self.pos;
+
+        while let Some(byte) = self.peek() {
+            if byte == b';' {
+                break;
+            }
+
+            self.pos += 1;
+        }
+
+        String::from_utf8_lossy(&self.input[start..self.pos]).trim().to_owned()
+    }
+}
+
+/// Decode an RFC 5987 extended value, `charset'language'percent-encoded`.
+fn decode_extended(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let encoded = parts.next()?;
+
+    let bytes: Vec<u8> = percent_decode(encoded.as_bytes()).collect();
+
+    if charset.eq_ignore_ascii_case("UTF-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("ISO-8859-1") {
+        Some(bytes.iter().map(|&byte| byte as char).collect())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContentDisposition;
+
+    fn parse(value: &str) -> (Option<String>, Option<String>) {
+        let disposition = ContentDisposition::parse(value).unwrap();
+
+        (
+            disposition.name().map(|name| name.to_owned()),
+            disposition.filename().map(|name| name.to_owned()),
+        )
+    }
+
+    fn some(name: &str, filename: &str) -> (Option<String>, Option<String>) {
+        (Some(name.to_owned()), Some(filename.to_owned()))
+    }
+
+    #[test]
+    fn browser_formats() {
+        // Chrome and Firefox send raw UTF-8, and percent-encode quotes and
+        // line breaks in file names.
+        assert_eq!(parse("form-data; name=\"file\"; filename=\"résumé 你好.pdf\""), some("file", "résumé 你好.pdf"));
+        assert_eq!(parse("form-data; name=\"file\"; filename=\"a%22b.txt\""), some("file", "a%22b.txt"));
+
+        // Safari escapes quotes with a backslash.
+        assert_eq!(parse(r#"form-data; name="file"; filename="a\"b.txt""#), some("file", "a\"b.txt"));
+
+        // Old Internet Explorer sends the full path.
+        assert_eq!(parse(r#"form-data; name="file"; filename="C:\Users\me\a.txt""#), some("file", r"C:\Users\me\a.txt"));
+
+        // Semicolons and equal signs inside quotes.
+        assert_eq!(parse("form-data; name=\"a;b=c\"; filename=\"x; y=z.txt\""), some("a;b=c", "x; y=z.txt"));
+
+        // A plain field.
+        assert_eq!(parse("form-data; name=\"title\""), (Some("title".to_owned()), None));
+        assert_eq!(parse("form-data; name=title"), (Some("title".to_owned()), None));
+    }
+
+    #[test]
+    fn extended_values() {
+        assert_eq!(
+            parse("form-data; name=\"file\"; filename=\"fallback.txt\"; filename*=UTF-8''%E2%82%AC%20rates.txt"),
+            some("file", "€ rates.txt")
+        );
+
+        // filename* wins whatever the order.
+        assert_eq!(
+            parse("form-data; name=file; filename*=utf-8'en'na%C3%AFve.txt; filename=naive.txt"),
+            some("file", "naïve.txt")
+        );
+
+        assert_eq!(parse("form-data; name=file; filename*=iso-8859-1''caf%E9.txt"), some("file", "café.txt"));
+
+        // Unknown charsets are dropped, falling back to filename.
+        assert_eq!(parse("form-data; name=file; filename=a.txt; filename*=koi8-r''%C1.txt"), some("file", "a.txt"));
+    }
+
+    #[test]
+    fn case_and_spacing() {
+        let disposition = ContentDisposition::parse("Form-Data ;NAME = \"file\" ; FileName=\"a.txt\";").unwrap();
+
+        assert_eq!(disposition.kind, "form-data");
+        assert_eq!(disposition.name(), Some("file"));
+        assert_eq!(disposition.filename(), Some("a.txt"));
+
+        assert!(ContentDisposition::parse("").is_none());
+        assert!(ContentDisposition::parse("; name=a").is_none());
+    }
+}
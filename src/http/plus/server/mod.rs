@@ -1,30 +1,95 @@
 use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use mime;
+use tempfile::TempPath;
 
-use crate::error::Result;
+pub use self::disposition::ContentDisposition;
+pub use self::multipart::{Multipart, Part, DEFAULT_SPOOL_THRESHOLD};
+pub use self::save::{sanitize_filename, Collision, SaveOptions, SavedFile};
+use crate::error::{Error, Result};
+use crate::http::body::BodyReader;
 use crate::http::request::Request;
 
+mod disposition;
+mod multipart;
+mod save;
+
 impl Request {
-    pub(crate) fn parse_formdata(&mut self) -> Option<FormData> {
+    /// Parse a buffered multipart body, a malformed one fails with
+    /// `Error::BadRequest`.
+    pub(crate) fn parse_formdata(&self) -> Result<Option<FormData>> {
         let content_type = match self.content_type() {
-            Some(c) => c.to_owned(),
-            None => return None,
+            Some(c) => c,
+            None => return Ok(None),
         };
 
         if content_type.type_() == mime::MULTIPART && content_type.subtype() == mime::FORM_DATA {
-            let boundary = if let Some(boundary) = content_type.get_param(mime::BOUNDARY) {
-                boundary.as_str()
-            } else {
-                return None;
+            let boundary = match content_type.get_param(mime::BOUNDARY) {
+                Some(boundary) => boundary.as_str(),
+                None => return Err(Error::BadRequest("Multipart body has no boundary".to_owned())),
             };
 
-            return FormData::parse(self.body(), boundary);
+            return FormData::multipart(self.body(), boundary, self.max_body_size()).parse().map(Some);
+        }
+
+        Ok(None)
+    }
+
+    /// Parse a streaming multipart body part by part, see `Route::streaming`.
+    ///
+    /// The total size is limited by the body size limit of the route.
+    /// Returns `None` if the body is not multipart/form-data, the route is
+    /// not streaming, or the body was already taken.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.post("/upload", |context| {
+    ///     let mut multipart = context.request.multipart().unwrap();
+    ///
+    ///     multipart.max_file_size(64 * 1024 * 1024);
+    ///
+    ///     let mut names = Vec::new();
+    ///
+    ///     while let Some(part) = multipart.next_part().unwrap() {
+    ///         names.push(part.name);
+    ///     }
+    ///
+    ///     context.response.from_text(names.join(",")).unwrap();
+    /// }).streaming();
+    ///
+    /// let response = app.test_client()
+    ///     .post("/upload")
+    ///     .header("Content-Type", "multipart/form-data; boundary=b")
+    ///     .body("--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--b--\r\n")
+    ///     .send();
+    ///
+    /// assert_eq!(response.get_body(), b"a");
+    /// ```
+    pub fn multipart(&mut self) -> Option<Multipart<BufReader<BodyReader>>> {
+        let content_type = self.content_type()?;
+
+        if content_type.type_() != mime::MULTIPART || content_type.subtype() != mime::FORM_DATA {
+            return None;
+        }
+
+        let boundary = content_type.get_param(mime::BOUNDARY)?.as_str().to_owned();
+        let limit = self.max_body_size();
+
+        let mut multipart = Multipart::new(BufReader::new(self.body_reader()?), &boundary);
+
+        if let Some(limit) = limit {
+            multipart.max_total_size(limit);
         }
 
-        None
+        Some(multipart)
     }
 }
 
@@ -44,38 +109,65 @@ impl FormData {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// File part of form data.
+///
+/// The files of a buffered body are in `data`. Read from a streaming body
+/// with `Multipart`, a file larger than the spool threshold is kept in a
+/// temporary file instead, which is removed once every clone of the part is
+/// dropped, and `data` is empty.
+#[derive(Clone, Debug)]
 pub struct FilePart {
     pub name: String,
     pub filename: String,
     pub content_type: mime::Mime,
+    /// Headers of the part, in order.
+    pub headers: Vec<(String, String)>,
     pub data: Vec<u8>,
+    /// Size of the file in bytes.
+    pub size: usize,
+    temp: Option<Arc<TempPath>>,
 }
 
-impl FilePart {
-    pub fn save_file<P: Into<PathBuf>>(&mut self, path: P) -> Result<PathBuf> {
-        let mut path_buf = path.into();
+impl PartialEq for FilePart {
+    fn eq(&self, other: &FilePart) -> bool {
+        self.name == other.name
+            && self.filename == other.filename
+            && self.content_type == other.content_type
+            && self.headers == other.headers
+            && self.data == other.data
+            && self.size == other.size
+            && self.path() == other.path()
+    }
+}
 
-        path_buf.push(self.filename.clone());
+impl FilePart {
+    /// A header of the part, the name is case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        multipart::find_header(&self.headers, name)
+    }
 
-        let path_buf2 = path_buf.clone();
+    /// Path of the temporary file, if the file was spooled.
+    pub fn path(&self) -> Option<&Path> {
+        self.temp.as_ref().map(|temp| temp.as_ref().as_ref())
+    }
 
-        let path = path_buf.as_path();
+    /// Whether the file was spooled to a temporary file.
+    pub fn is_spooled(&self) -> bool {
+        self.temp.is_some()
+    }
 
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+    /// Read the file, from memory or the temporary file.
+    pub fn read_data(&self) -> Result<Vec<u8>> {
+        match self.path() {
+            Some(path) => Ok(fs::read(path)?),
+            None => Ok(self.data.clone()),
         }
+    }
 
-        let mut open_option = fs::OpenOptions::new();
-
-        open_option.write(true).create(true);
-
-        let mut file = open_option.open(path)?;
-
-        file.write_all(&self.data)?;
-        file.flush()?;
-
-        Ok(path_buf2)
+    /// Save the file into the directory `path` under its sanitized name,
+    /// replacing an existing file, see `FilePart::save`.
+    pub fn save_file<P: Into<PathBuf>>(&mut self, path: P) -> Result<PathBuf> {
+        Ok(self.save(&SaveOptions::new(path))?.path)
     }
 }
 
@@ -88,136 +180,100 @@ impl FormData {
     }
 
     pub fn parse(body: &[u8], boundary: &str) -> Option<FormData> {
-        let boundary = "--".to_owned() + boundary;
-
-        let mut form_data = FormData::new();
-
-        {
-            if !has_boundary(body, &boundary) {
-                return None;
-            }
-
-            if body.len() <= boundary.len() + 2 {
-                return None;
-            }
-
-            let mut part: Vec<(usize, usize)> = Vec::new();
-
-            let mut cursor = boundary.len() + 2;
-
-            loop {
-                match twoway::find_bytes(&body[cursor..], boundary.as_bytes()) {
-                    Some(index) => {
-                        if index == 0 {
-                            break;
-                        }
-
-                        if &body[cursor + index - 2..cursor + index] != b"\r\n" {
-                            return None;
-                        }
-
-                        part.push((cursor, cursor + index - 2));
-
-                        cursor = cursor + boundary.len() + 2 + index;
-
-                        if cursor > body.len() {
-                            return None;
-                        }
-                    }
-                    None => {
-                        if cursor == body.len() && &body[cursor - 2..cursor] == b"--" {
-                            break;
-                        }
-
-                        if cursor == body.len() - 2 && &body[cursor - 2..cursor] == b"--" {
-                            break;
-                        }
-
-                        return None;
-                    }
-                }
-            }
-
-            for (start, end) in part {
-                let mut headers = [httparse::EMPTY_HEADER; 4];
-                match httparse::parse_headers(&body[start..end], &mut headers) {
-                    Ok(httparse::Status::Complete((index, raw_headers))) => {
-                        if let Some(value) =
-                            get_value_from_header(raw_headers, "Content-Disposition")
-                        {
-                            let ss: Vec<&str> = value.split(";").collect();
-
-                            let mut name = "";
-                            let mut filename = None;
-
-                            for s in ss {
-                                let s = s.trim();
-                                if s.starts_with("name") {
-                                    name = &s[6..s.len() - 1];
-                                } else if s.starts_with("filename") {
-                                    filename = Some(&s[10..s.len() - 1]);
-                                }
-                            }
-
-                            // is file
-                            if let Some(filename) = filename {
-                                let content_type = {
-                                    if let Some(value) =
-                                        get_value_from_header(raw_headers, "Content-Type")
-                                    {
-                                        value.parse().unwrap_or(mime::APPLICATION_OCTET_STREAM)
-                                    } else {
-                                        mime::APPLICATION_OCTET_STREAM
-                                    }
-                                };
-
-                                let file_part = FilePart {
-                                    name: name.to_string(),
-                                    filename: filename.to_owned(),
-                                    content_type: content_type,
-                                    data: body[start + index..end].to_vec(),
-                                };
-
-                                form_data.files.push(file_part);
-                            } else {
-                                form_data.fields.push((
-                                    name.to_string(),
-                                    String::from_utf8_lossy(&body[start + index..end]).into_owned(),
-                                ));
-                            }
-                        } else {
-                            return None;
-                        }
-                    }
-                    Ok(httparse::Status::Partial) => return None,
-                    Err(_) => return None,
-                }
-            }
-        }
+        FormData::parse_with_limit(body, boundary, None)
+    }
 
-        Some(form_data)
+    /// Parse multipart form data with `Multipart`, giving up if the body is
+    /// larger than `limit`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::http::plus::server::FormData;
+    ///
+    /// let body = b"--boundary\r\n\
+    ///     Content-Disposition: form-data; name=\"name\"\r\n\r\n\
+    ///     sincere\r\n\
+    ///     --boundary--\r\n";
+    ///
+    /// assert!(FormData::parse_with_limit(body, "boundary", Some(1024)).is_some());
+    /// assert!(FormData::parse_with_limit(body, "boundary", Some(16)).is_none());
+    /// ```
+    pub fn parse_with_limit(body: &[u8], boundary: &str, limit: Option<usize>) -> Option<FormData> {
+        FormData::multipart(body, boundary, limit).parse().ok()
     }
-}
 
-fn has_boundary(body: &[u8], boundary: &str) -> bool {
-    match twoway::find_bytes(body, boundary.as_bytes()) {
-        Some(index) => {
-            if index == 0 {
-                return true;
-            } else {
-                return false;
-            }
+    /// A parser for a body in memory, which keeps its files in memory too.
+    fn multipart<'a>(body: &'a [u8], boundary: &str, limit: Option<usize>) -> Multipart<&'a [u8]> {
+        let mut multipart = Multipart::new(body, boundary);
+        multipart.spool_threshold(usize::MAX);
+
+        if let Some(limit) = limit {
+            multipart.max_total_size(limit);
         }
-        None => return false,
+
+        multipart
     }
 }
 
 fn get_value_from_header<'a>(headers: &'a [httparse::Header], key: &str) -> Option<String> {
     for header in headers {
-        if header.name == key {
+        if header.name.eq_ignore_ascii_case(key) {
             return Some(String::from_utf8_lossy(header.value).to_string());
         }
     }
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::App;
+
+    #[test]
+    fn buffered() {
+        let mut app = App::new();
+
+        app.post("/", |context| {
+            let name = context.request.post("name").unwrap_or_default();
+            let file = &context.request.files()[0];
+
+            format!("{} {} {}", name, file.data.len(), file.is_spooled())
+        });
+
+        app.on_error(|context, err| {
+            let text = format!("{}: {}", err.status_code(), err);
+            context.response.status_code(err.status_code()).from_text(text).unwrap();
+        });
+
+        let client = app.test_client();
+
+        let data = "x".repeat(300 * 1024);
+        let body = format!(
+            "--b\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\nsincere\r\n\
+             --b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\r\n{}\r\n--b--\r\n",
+            data
+        );
+
+        let response = client
+            .post("/")
+            .header("Content-Type", "multipart/form-data; boundary=b")
+            .body(body.clone())
+            .send();
+        assert_eq!(response.get_body(), format!("sincere {} false", data.len()).as_bytes());
+
+        let response = client
+            .post("/")
+            .header("Content-Type", "multipart/form-data; boundary=b")
+            .body(&body[..200])
+            .send();
+        assert_eq!(response.get_body(), b"400: Multipart body is malformed");
+
+        let response = client
+            .post("/")
+            .header("Content-Type", "multipart/form-data")
+            .body(body)
+            .send();
+        assert_eq!(response.get_body(), b"400: Multipart body has no boundary");
+    }
+}
@@ -0,0 +1,534 @@
+//! Streaming multipart/form-data parser.
+use std::io::{self, BufRead, Read, Write};
+use std::sync::Arc;
+
+use tempfile::{NamedTempFile, TempPath};
+
+use super::{get_value_from_header, ContentDisposition, FilePart, FormData};
+use crate::error::{Error, Result};
+use crate::util::buf_read_ext::BufReadExt;
+
+/// Parts larger than this are spooled to a temporary file by default.
+pub const DEFAULT_SPOOL_THRESHOLD: usize = 256 * 1024;
+
+/// Limit of the headers of a part.
+const MAX_HEADER_SIZE: usize = 8 * 1024;
+
+/// Maximum number of headers of a part.
+const MAX_HEADERS: usize = 32;
+
+/// Streaming multipart/form-data parser.
+///
+/// Parts are read one by one from the reader, file parts larger than the
+/// spool threshold are written to temporary files instead of memory.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+///
+/// use sincere::http::plus::server::Multipart;
+///
+/// let body = "--boundary\r\n\
+///     Content-Disposition: form-data; name=\"name\"\r\n\r\n\
+///     sincere\r\n\
+///     --boundary\r\n\
+///     Content-Disposition: form-data; name=\"file\"; filename=\"hello.txt\"\r\n\
+///     Content-Type: text/plain\r\n\r\n\
+///     Hello world!\r\n\
+///     --boundary--\r\n";
+///
+/// let mut multipart = Multipart::new(Cursor::new(body), "boundary");
+///
+/// multipart.max_file_size(1024).spool_threshold(4);
+///
+/// let form_data = multipart.parse().unwrap();
+///
+/// assert_eq!(form_data.fields, vec![("name".to_owned(), "sincere".to_owned())]);
+///
+/// let file = &form_data.files[0];
+///
+/// assert_eq!(file.filename, "hello.txt");
+/// assert!(file.is_spooled());
+/// assert_eq!(file.read_data().unwrap(), b"Hello world!");
+/// ```
+pub struct Multipart<R> {
+    reader: Counted<R>,
+    boundary: Vec<u8>,
+    max_file_size: Option<usize>,
+    max_field_size: Option<usize>,
+    spool_threshold: usize,
+    started: bool,
+    finished: bool,
+}
+
+/// A part of a multipart body.
+#[derive(Debug)]
+pub struct Part {
+    /// Name from `Content-Disposition`.
+    pub name: String,
+    /// File name from `Content-Disposition`, `None` for a plain field.
+    pub filename: Option<String>,
+    /// Headers of the part, in order.
+    pub headers: Vec<(String, String)>,
+    data: Vec<u8>,
+    temp: Option<TempPath>,
+    size: usize,
+}
+
+impl<R: BufRead> Multipart<R> {
+    /// Create a parser for a body with the `boundary` of its `Content-Type`.
+    pub fn new(reader: R, boundary: &str) -> Multipart<R> {
+        Multipart {
+            reader: Counted {
+                inner: reader,
+                read: 0,
+                limit: None,
+            },
+            boundary: format!("\r\n--{}", boundary).into_bytes(),
+            max_file_size: None,
+            max_field_size: None,
+            spool_threshold: DEFAULT_SPOOL_THRESHOLD,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Limit the size of each file part.
+    pub fn max_file_size(&mut self, size: usize) -> &mut Self {
+        self.max_file_size = Some(size);
+        self
+    }
+
+    /// Limit the size of each field, which is kept in memory.
+    pub fn max_field_size(&mut self, size: usize) -> &mut Self {
+        self.max_field_size = Some(size);
+        self
+    }
+
+    /// Limit the size of the whole body, headers and boundaries included.
+    pub fn max_total_size(&mut self, size: usize) -> &mut Self {
+        self.reader.limit = Some(size);
+        self
+    }
+
+    /// File parts larger than `size` are spooled to a temporary file.
+    pub fn spool_threshold(&mut self, size: usize) -> &mut Self {
+        self.spool_threshold = size;
+        self
+    }
+
+    /// Read the next part, `None` after the last one.
+    ///
+    /// Going over a limit fails with `Error::PayloadTooLarge`, a malformed
+    /// body with `Error::BadRequest`.
+    pub fn next_part(&mut self) -> Result<Option<Part>> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        if !self.started {
+            // The first boundary has no leading CRLF, and may follow a preamble.
+            let token = self.boundary[2..].to_vec();
+            self.read_head(&token)?;
+
+            self.started = true;
+        }
+
+        let mut next = [0; 2];
+        self.read_exact(&mut next)?;
+
+        if &next == b"--" {
+            self.finished = true;
+            return Ok(None);
+        }
+
+        if &next != b"\r\n" {
+            return Err(malformed());
+        }
+
+        let mut head = self.read_head(b"\r\n\r\n")?;
+        head.extend_from_slice(b"\r\n\r\n");
+
+        let mut raw_headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+
+        let headers = match httparse::parse_headers(&head, &mut raw_headers) {
+            Ok(httparse::Status::Complete((_, raw_headers))) => raw_headers,
+            _ => return Err(malformed()),
+        };
+
+        let disposition = get_value_from_header(headers, "Content-Disposition")
+            .and_then(|value| ContentDisposition::parse(&value))
+            .ok_or_else(malformed)?;
+
+        let name = disposition.name().unwrap_or_default().to_owned();
+        let filename = disposition.filename().map(|filename| filename.to_owned());
+
+        let headers = headers
+            .iter()
+            .map(|header| {
+                (header.name.to_owned(), String::from_utf8_lossy(header.value).into_owned())
+            })
+            .collect();
+
+        let mut spool = if filename.is_some() {
+            Spool::new(self.spool_threshold, self.max_file_size)
+        } else {
+            Spool::new(usize::MAX, self.max_field_size)
+        };
+
+        let token = self.boundary.clone();
+
+        if !self.read_until(&token, &mut spool)? {
+            return Err(malformed());
+        }
+
+        Ok(Some(Part {
+            name,
+            filename,
+            headers,
+            size: spool.size,
+            data: spool.data,
+            temp: spool.file.map(|file| file.into_temp_path()),
+        }))
+    }
+
+    /// Read every part into form data.
+    pub fn parse(mut self) -> Result<FormData> {
+        let mut form_data = FormData::new();
+
+        while let Some(part) = self.next_part()? {
+            match part.filename {
+                Some(_) => form_data.files.push(part.into_file_part()),
+                None => form_data.fields.push((part.name, String::from_utf8_lossy(&part.data).into_owned())),
+            }
+        }
+
+        Ok(form_data)
+    }
+
+    /// Read the preamble or the headers of a part, up to `token`. Going over
+    /// their limit makes the body malformed rather than too large.
+    fn read_head(&mut self, token: &[u8]) -> Result<Vec<u8>> {
+        let mut head = Spool::new(usize::MAX, Some(MAX_HEADER_SIZE));
+
+        match self.read_until(token, &mut head) {
+            Ok(true) => Ok(head.data),
+            Ok(false) => Err(malformed()),
+            Err(Error::PayloadTooLarge(limit)) if Some(limit) != self.reader.limit => Err(malformed()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn read_until(&mut self, token: &[u8], spool: &mut Spool) -> Result<bool> {
+        let found = match self.reader.stream_until_token(token, spool) {
+            Ok((_, found)) => found,
+            Err(err) => return Err(self.limit_error(spool).unwrap_or_else(|| err.into())),
+        };
+
+        // The last buffer may have been consumed at once.
+        match self.limit_error(spool) {
+            Some(err) => Err(err),
+            None => Ok(found),
+        }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => Ok(()),
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => Err(malformed()),
+            Err(err) => Err(self.limit_error(&Spool::new(0, None)).unwrap_or_else(|| err.into())),
+        }
+    }
+
+    fn limit_error(&self, spool: &Spool) -> Option<Error> {
+        match (self.reader.limit, spool.max_size) {
+            (Some(limit), _) if self.reader.read > limit => Some(Error::PayloadTooLarge(limit)),
+            (_, Some(limit)) if spool.size > limit => Some(Error::PayloadTooLarge(limit)),
+            _ => None,
+        }
+    }
+}
+
+impl Part {
+    /// A header of the part, the name is case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Size of the data in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the data was spooled to a temporary file.
+    pub fn is_spooled(&self) -> bool {
+        self.temp.is_some()
+    }
+
+    /// Read the data, from memory or the temporary file.
+    pub fn read_data(&self) -> Result<Vec<u8>> {
+        match self.temp {
+            Some(ref temp) => Ok(std::fs::read(temp)?),
+            None => Ok(self.data.clone()),
+        }
+    }
+
+    /// Convert into a file part, the content type defaults to
+    /// `application/octet-stream`.
+    pub fn into_file_part(self) -> FilePart {
+        let content_type = self.header("Content-Type")
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+
+        FilePart {
+            name: self.name,
+            filename: self.filename.unwrap_or_default(),
+            content_type,
+            headers: self.headers,
+            data: self.data,
+            size: self.size,
+            temp: self.temp.map(Arc::new),
+        }
+    }
+}
+
+pub(super) fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn malformed() -> Error {
+    Error::BadRequest("Multipart body is malformed".to_owned())
+}
+
+/// Reader counting the consumed bytes, failing once over the limit.
+struct Counted<R> {
+    inner: R,
+    read: usize,
+    limit: Option<usize>,
+}
+
+impl<R: BufRead> Counted<R> {
+    fn check(&self) -> io::Result<()> {
+        match self.limit {
+            Some(limit) if self.read > limit => Err(io::Error::other("Multipart body too large")),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<R: BufRead> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check()?;
+        let len = self.inner.read(buf)?;
+        self.read += len;
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for Counted<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check()?;
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.read += amt;
+        self.inner.consume(amt)
+    }
+}
+
+/// Writer keeping data in memory up to a threshold, then in a temporary file.
+struct Spool {
+    data: Vec<u8>,
+    file: Option<NamedTempFile>,
+    size: usize,
+    threshold: usize,
+    max_size: Option<usize>,
+}
+
+impl Spool {
+    fn new(threshold: usize, max_size: Option<usize>) -> Spool {
+        Spool {
+            data: Vec::new(),
+            file: None,
+            size: 0,
+            threshold,
+            max_size,
+        }
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.size += buf.len();
+
+        if let Some(max_size) = self.max_size {
+            if self.size > max_size {
+                return Err(io::Error::other("Multipart part too large"));
+            }
+        }
+
+        match self.file {
+            Some(ref mut file) => file.write_all(buf)?,
+            None if self.size > self.threshold => {
+                let mut file = NamedTempFile::new()?;
+                file.write_all(&self.data)?;
+                file.write_all(buf)?;
+
+                self.data = Vec::new();
+                self.file = Some(file);
+            }
+            None => self.data.extend_from_slice(buf),
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file {
+            Some(ref mut file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use super::Multipart;
+    use crate::error::Error;
+
+    fn body(file_size: usize) -> Vec<u8> {
+        let mut body = b"preamble\r\n--boundary\r\n\
+            Content-Disposition: form-data; name=\"name\"\r\n\r\n\
+            sincere\r\n\
+            --boundary\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\n\
+            Content-Type: application/x-test\r\n\
+            X-Extra: 1\r\n\r\n"
+            .to_vec();
+
+        body.extend((0..file_size).map(|i| (i % 251) as u8));
+        body.extend_from_slice(b"\r\n--boundary--\r\n");
+        body
+    }
+
+    #[test]
+    fn parts_and_spooling() {
+        // A small buffer makes boundaries straddle reads.
+        let reader = BufReader::with_capacity(7, Cursor::new(body(100_000)));
+
+        let mut multipart = Multipart::new(reader, "boundary");
+        multipart.spool_threshold(1024);
+
+        let field = multipart.next_part().unwrap().unwrap();
+        assert_eq!(field.name, "name");
+        assert_eq!(field.filename, None);
+        assert_eq!(field.read_data().unwrap(), b"sincere");
+        assert!(!field.is_spooled());
+
+        let file = multipart.next_part().unwrap().unwrap();
+        assert_eq!(file.filename.as_deref(), Some("a.bin"));
+        assert_eq!(file.size(), 100_000);
+        assert!(file.is_spooled());
+        assert_eq!(file.headers[2], ("X-Extra".to_owned(), "1".to_owned()));
+
+        let data = file.read_data().unwrap();
+        assert!(data.iter().enumerate().all(|(i, b)| *b == (i % 251) as u8));
+
+        let file = file.into_file_part();
+        assert_eq!(file.content_type.as_ref(), "application/x-test");
+
+        assert!(multipart.next_part().unwrap().is_none());
+    }
+
+    #[test]
+    fn limits() {
+        let mut multipart = Multipart::new(Cursor::new(body(1000)), "boundary");
+        multipart.max_file_size(999);
+
+        match multipart.parse() {
+            Err(Error::PayloadTooLarge(999)) => (),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+
+        let mut multipart = Multipart::new(Cursor::new(body(1000)), "boundary");
+        multipart.max_total_size(1000);
+
+        match multipart.parse() {
+            Err(Error::PayloadTooLarge(1000)) => (),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+
+        let mut multipart = Multipart::new(Cursor::new(body(1000)), "boundary");
+        multipart.max_file_size(1000).max_total_size(2000);
+
+        assert_eq!(multipart.parse().unwrap().files[0].size, 1000);
+    }
+
+    #[test]
+    fn browser_bodies() {
+        // As sent by Chrome, raw UTF-8 in the file name.
+        let chrome = "------WebKitFormBoundaryx8Hb2nGkRWdjDQfC\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            Ünïcödé\r\n\
+            ------WebKitFormBoundaryx8Hb2nGkRWdjDQfC\r\n\
+            Content-Disposition: form-data; name=\"upload\"; filename=\"报告 2020.pdf\"\r\n\
+            Content-Type: application/pdf\r\n\r\n\
+            %PDF\r\n\
+            ------WebKitFormBoundaryx8Hb2nGkRWdjDQfC--\r\n";
+
+        let form_data = Multipart::new(Cursor::new(chrome), "----WebKitFormBoundaryx8Hb2nGkRWdjDQfC")
+            .parse()
+            .unwrap();
+
+        assert_eq!(form_data.fields, vec![("title".to_owned(), "Ünïcödé".to_owned())]);
+        assert_eq!(form_data.files[0].filename, "报告 2020.pdf");
+        assert_eq!(form_data.files[0].content_type, mime::APPLICATION_PDF);
+        assert_eq!(form_data.files[0].data, b"%PDF");
+
+        // Lowercase header names, extra headers and an extended file name.
+        let client = "--b\r\n\
+            content-disposition: form-data; name=\"doc\"; filename=\"x.txt\"; filename*=UTF-8''%E2%82%AC.txt\r\n\
+            content-type: text/plain; charset=utf-8\r\n\
+            Content-Transfer-Encoding: binary\r\n\
+            Content-ID: <part1>\r\n\
+            X-One: 1\r\n\
+            X-Two: 2\r\n\r\n\
+            euro\r\n\
+            --b--";
+
+        let mut multipart = Multipart::new(Cursor::new(client), "b");
+
+        let part = multipart.next_part().unwrap().unwrap();
+        assert_eq!(part.filename.as_deref(), Some("€.txt"));
+        assert_eq!(part.header("CONTENT-TRANSFER-ENCODING"), Some("binary"));
+        assert_eq!(part.headers.len(), 6);
+
+        let file = part.into_file_part();
+        assert_eq!(file.content_type.essence_str(), "text/plain");
+        assert_eq!(file.header("content-id"), Some("<part1>"));
+
+        assert!(multipart.next_part().unwrap().is_none());
+    }
+
+    #[test]
+    fn malformed() {
+        let truncated = b"--boundary\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nno end";
+        let headless = b"--boundary\r\nContent-Disposition: form-da";
+        let unterminated = b"--boundary";
+        let preamble = [&[b'x'; 9000][..], b"\r\n--boundary--"].concat();
+
+        for body in &[&truncated[..], &headless[..], &unterminated[..], &b"nothing"[..], &preamble[..]] {
+            match Multipart::new(Cursor::new(*body), "boundary").parse() {
+                Err(err @ Error::BadRequest(_)) => assert_eq!(err.status_code(), 400),
+                other => panic!("{:?}", other.map(|_| ())),
+            }
+        }
+    }
+}
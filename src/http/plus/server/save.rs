@@ -0,0 +1,429 @@
+//! Saving uploaded files.
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use mime::Mime;
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+
+use super::FilePart;
+use crate::error::{Error, Result};
+use crate::http::plus::random_alphanumeric;
+
+/// Longest file name kept, in bytes.
+const MAX_NAME_LEN: usize = 200;
+
+/// Attempts to find a free name with `Collision::Rename`.
+const MAX_RENAMES: usize = 1000;
+
+/// What to do when the target file already exists.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Collision {
+    /// Replace the existing file.
+    Overwrite,
+    /// Save as `name (1).ext`, `name (2).ext`, and so on.
+    Rename,
+    /// Fail with an `AlreadyExists` io error.
+    Fail,
+}
+
+#[derive(Clone, Debug)]
+enum Naming {
+    Sanitized,
+    Generated,
+    Fixed(String),
+}
+
+/// Options of `FilePart::save`.
+///
+/// By default the client file name is sanitized, every content type is
+/// accepted and an existing file is replaced.
+///
+/// # Examples
+///
+/// ```no_run
+/// use sincere::App;
+/// use sincere::http::plus::server::{Collision, SaveOptions};
+///
+/// let mut app = App::new();
+///
+/// app.post("/avatar", |context| {
+///     let mut options = SaveOptions::new("./uploads");
+///
+///     options
+///         .generate_name()
+///         .allow_type(mime::IMAGE_STAR)
+///         .collision(Collision::Fail);
+///
+///     let saved = context.request.files()[0].save(&options).unwrap();
+///
+///     context.response.from_json(&saved.sha256).unwrap();
+/// });
+/// ```
+#[derive(Clone, Debug)]
+pub struct SaveOptions {
+    dir: PathBuf,
+    naming: Naming,
+    allowed_types: Vec<Mime>,
+    collision: Collision,
+}
+
+/// A file saved by `FilePart::save`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedFile {
+    /// Path of the saved file.
+    pub path: PathBuf,
+    /// File name within the target directory.
+    pub file_name: String,
+    /// Size in bytes.
+    pub size: u64,
+    /// Lowercase hex SHA-256 of the content.
+    pub sha256: String,
+    /// Content type declared by the client.
+    pub content_type: Mime,
+}
+
+impl SaveOptions {
+    /// Save into `dir`, which is created if needed.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> SaveOptions {
+        SaveOptions {
+            dir: dir.into(),
+            naming: Naming::Sanitized,
+            allowed_types: Vec::new(),
+            collision: Collision::Overwrite,
+        }
+    }
+
+    /// Use a random name, keeping the extension of the client file name.
+    pub fn generate_name(&mut self) -> &mut SaveOptions {
+        self.naming = Naming::Generated;
+        self
+    }
+
+    /// Use the given name, it is sanitized too.
+    pub fn name<S: Into<String>>(&mut self, name: S) -> &mut SaveOptions {
+        self.naming = Naming::Fixed(name.into());
+        self
+    }
+
+    /// Accept this content type, `image/*` style types accept a whole
+    /// family. Once a type is allowed, other types are refused.
+    pub fn allow_type(&mut self, content_type: Mime) -> &mut SaveOptions {
+        self.allowed_types.push(content_type);
+        self
+    }
+
+    /// Set the collision policy.
+    pub fn collision(&mut self, collision: Collision) -> &mut SaveOptions {
+        self.collision = collision;
+        self
+    }
+
+    fn allows(&self, content_type: &Mime) -> bool {
+        self.allowed_types.is_empty()
+            || self.allowed_types.iter().any(|allowed| {
+                allowed.type_() == content_type.type_()
+                    && (allowed.subtype() == mime::STAR || allowed.subtype() == content_type.subtype())
+            })
+    }
+}
+
+impl FilePart {
+    /// Save the file, writing to a temporary file in the target directory
+    /// first and renaming it into place, so that readers never see a
+    /// partial file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use sincere::App;
+    /// use sincere::http::plus::server::SaveOptions;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.post("/upload", |context| {
+    ///     for file in context.request.files() {
+    ///         let saved = file.save(&SaveOptions::new("./uploads")).unwrap();
+    ///         println!("{:?}: {} bytes", saved.path, saved.size);
+    ///     }
+    /// });
+    /// ```
+    pub fn save(&self, options: &SaveOptions) -> Result<SavedFile> {
+        if !options.allows(&self.content_type) {
+            return Err(Error::Error(format!(
+                "Content type is not allowed: {}",
+                self.content_type
+            )));
+        }
+
+        let file_name = match options.naming {
+            Naming::Sanitized => sanitize(&self.filename),
+            Naming::Fixed(ref name) => sanitize(name),
+            Naming::Generated => None,
+        };
+
+        let file_name = match file_name {
+            Some(file_name) => file_name,
+            None => generate(&self.filename, &self.content_type),
+        };
+
+        fs::create_dir_all(&options.dir)?;
+
+        let mut temp = NamedTempFile::new_in(&options.dir)?;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+
+        {
+            let mut reader: Box<dyn Read> = match self.path() {
+                Some(path) => Box::new(fs::File::open(path)?),
+                None => Box::new(&self.data[..]),
+            };
+
+            let mut buf = [0; 16 * 1024];
+
+            loop {
+                let len = reader.read(&mut buf)?;
+                if len == 0 {
+                    break;
+                }
+
+                hasher.update(&buf[..len]);
+                temp.write_all(&buf[..len])?;
+                size += len as u64;
+            }
+        }
+
+        temp.as_file().sync_all()?;
+
+        let (path, file_name) = persist(temp, &options.dir, file_name, options.collision)?;
+
+        Ok(SavedFile {
+            path,
+            file_name,
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+            content_type: self.content_type.clone(),
+        })
+    }
+}
+
+fn persist(
+    mut temp: NamedTempFile,
+    dir: &Path,
+    file_name: String,
+    collision: Collision,
+) -> Result<(PathBuf, String)> {
+    if collision == Collision::Overwrite {
+        let path = dir.join(&file_name);
+        temp.persist(&path).map_err(|err| err.error)?;
+        return Ok((path, file_name));
+    }
+
+    let (stem, extension) = split_extension(&file_name);
+
+    for attempt in 0..MAX_RENAMES {
+        let name = match attempt {
+            0 => file_name.clone(),
+            _ => format!("{} ({}){}", stem, attempt, extension),
+        };
+
+        let path = dir.join(&name);
+
+        match temp.persist_noclobber(&path) {
+            Ok(_) => return Ok((path, name)),
+            Err(err) => {
+                if err.error.kind() != io::ErrorKind::AlreadyExists || collision == Collision::Fail {
+                    return Err(err.error.into());
+                }
+
+                temp = err.file;
+            }
+        }
+    }
+
+    Err(Error::Error(format!("No free name for {}", file_name)))
+}
+
+/// Make a client file name safe to use in a directory, `None` if nothing
+/// usable is left.
+///
+/// Only the last path component is kept, control characters and characters
+/// reserved on Windows are dropped, and reserved device names are prefixed.
+///
+/// # Examples
+///
+/// ```
+/// use sincere::http::plus::server::sanitize_filename;
+///
+/// assert_eq!(sanitize_filename("../../etc/cron.d/x").as_deref(), Some("x"));
+/// assert_eq!(sanitize_filename(r"C:\Users\me\photo.jpg").as_deref(), Some("photo.jpg"));
+/// assert_eq!(sanitize_filename("con.txt").as_deref(), Some("_con.txt"));
+/// assert_eq!(sanitize_filename(".."), None);
+/// ```
+pub fn sanitize_filename(name: &str) -> Option<String> {
+    sanitize(name)
+}
+
+fn sanitize(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+
+    let name: String = name
+        .chars()
+        .filter(|&c| !c.is_control() && !"<>:\"|?*".contains(c))
+        .collect();
+
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut name = truncate(name, MAX_NAME_LEN);
+
+    let stem = name.split('.').next().unwrap_or_default().to_ascii_uppercase();
+
+    let reserved = matches!(&*stem, "CON" | "PRN" | "AUX" | "NUL")
+        || ((stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.len() == 4
+            && stem.as_bytes()[3].is_ascii_digit());
+
+    if reserved {
+        name.insert(0, '_');
+    }
+
+    Some(name)
+}
+
+/// Keep at most `len` bytes, cutting the stem rather than the extension.
+fn truncate(name: &str, len: usize) -> String {
+    if name.len() <= len {
+        return name.to_owned();
+    }
+
+    let (stem, extension) = split_extension(name);
+    let extension = if extension.len() < len / 2 { extension } else { "" };
+
+    let mut end = len - extension.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}{}", &stem[..end], extension)
+}
+
+/// A random name, with the extension of the client file name if it is
+/// plain, or else one known for the content type.
+fn generate(filename: &str, content_type: &Mime) -> String {
+    let (_, extension) = split_extension(filename);
+
+    let plain = extension.len() > 1
+        && extension.len() <= 16
+        && extension[1..].chars().all(|c| c.is_ascii_alphanumeric());
+
+    let extension = if plain {
+        extension.to_ascii_lowercase()
+    } else {
+        mime_guess::get_mime_extensions(content_type)
+            .and_then(|extensions| extensions.first())
+            .map(|extension| format!(".{}", extension))
+            .unwrap_or_default()
+    };
+
+    format!("{}{}", random_alphanumeric(32), extension)
+}
+
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(index) if index > 0 => name.split_at(index),
+        _ => (name, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{sanitize, Collision, SaveOptions};
+    use crate::http::plus::server::FilePart;
+
+    fn part(filename: &str, data: &[u8]) -> FilePart {
+        FilePart {
+            name: "file".to_owned(),
+            filename: filename.to_owned(),
+            content_type: mime::TEXT_PLAIN,
+            headers: Vec::new(),
+            data: data.to_vec(),
+            size: data.len(),
+            temp: None,
+        }
+    }
+
+    #[test]
+    fn sanitize_names() {
+        assert_eq!(sanitize("a.txt").as_deref(), Some("a.txt"));
+        assert_eq!(sanitize("../../etc/cron.d/x").as_deref(), Some("x"));
+        assert_eq!(sanitize("..\\..\\boot.ini").as_deref(), Some("boot.ini"));
+        assert_eq!(sanitize("a\0b\r\n<c>.txt").as_deref(), Some("abc.txt"));
+        assert_eq!(sanitize(" .hidden. ").as_deref(), Some("hidden"));
+        assert_eq!(sanitize("LPT1").as_deref(), Some("_LPT1"));
+        assert_eq!(sanitize("报告.pdf").as_deref(), Some("报告.pdf"));
+        assert_eq!(sanitize("dir/"), None);
+
+        let long = sanitize(&format!("{}.txt", "é".repeat(300))).unwrap();
+        assert!(long.len() <= 200);
+        assert!(long.ends_with("é.txt"));
+    }
+
+    #[test]
+    fn save() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let saved = part("../../escape.txt", b"Hello world!")
+            .save(&SaveOptions::new(dir.path()))
+            .unwrap();
+
+        assert_eq!(saved.path, dir.path().join("escape.txt"));
+        assert_eq!(saved.size, 12);
+        assert_eq!(saved.sha256, "c0535e4be2b79ffd93291305436bf889314e4a3faec05ecffcbb7df31ad9e51a");
+
+        // Overwriting with a shorter file leaves no trailing bytes.
+        part("escape.txt", b"Hi").save(&SaveOptions::new(dir.path())).unwrap();
+        assert_eq!(fs::read(dir.path().join("escape.txt")).unwrap(), b"Hi");
+
+        let mut options = SaveOptions::new(dir.path());
+        options.collision(Collision::Rename);
+
+        let saved = part("escape.txt", b"1").save(&options).unwrap();
+        assert_eq!(saved.file_name, "escape (1).txt");
+        let saved = part("escape.txt", b"2").save(&options).unwrap();
+        assert_eq!(saved.file_name, "escape (2).txt");
+
+        options.collision(Collision::Fail);
+        assert!(part("escape.txt", b"3").save(&options).is_err());
+        assert_eq!(fs::read(dir.path().join("escape.txt")).unwrap(), b"Hi");
+
+        let mut options = SaveOptions::new(dir.path());
+        options.generate_name();
+
+        let saved = part("photo.JPG", b"").save(&options).unwrap();
+        assert_eq!(saved.file_name.len(), 36);
+        assert!(saved.file_name.ends_with(".jpg"));
+
+        let mut file = part("noext", b"");
+        file.content_type = mime::IMAGE_PNG;
+
+        let saved = file.save(&options).unwrap();
+        assert!(saved.file_name.ends_with(".png"));
+
+        let mut options = SaveOptions::new(dir.path());
+        options.allow_type(mime::IMAGE_STAR);
+        assert!(part("a.txt", b"").save(&options).is_err());
+
+        options.allow_type(mime::TEXT_PLAIN);
+        assert!(part("a.txt", b"").save(&options).is_ok());
+
+        // Nothing but the saved files is left behind.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 6);
+    }
+}
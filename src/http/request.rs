@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use hyper::header::CONTENT_TYPE;
+use hyper::header::{CONTENT_TYPE, COOKIE};
 use hyper::{self, HeaderMap, Method, Uri, Version};
 use hyper::body::Bytes;
 use http::request::Parts;
@@ -8,6 +8,9 @@ use mime::{self, Mime};
 use serde::de::DeserializeOwned;
 use serde_json;
 
+use super::bind::{BindError, Location};
+use super::body::{BodyReader, BodyStream};
+use super::cookie::{Cookie, Key};
 use super::plus::server::FilePart;
 use crate::error::Result;
 use crate::util::url;
@@ -22,11 +25,27 @@ pub struct Request {
     querys: Vec<(String, String)>,
     posts: Vec<(String, String)>,
     files: Vec<FilePart>,
-    body: Bytes
+    body: Bytes,
+    stream: Option<BodyStream>,
+    max_body_size: Option<usize>,
+    pub(crate) key: Option<Key>
+}
+
+/// Request body as handed over by the app.
+pub(crate) enum Payload {
+    /// The body is read into memory, form data is parsed.
+    Buffered(Bytes),
+    /// The body is left to the handle to read.
+    Stream(hyper::Body),
 }
 
 impl Request {
-    pub(crate) fn from_hyper_request(parts: Parts, body: Bytes) -> Request {
+    pub(crate) fn from_hyper_request(parts: Parts, payload: Payload, max_body_size: Option<usize>) -> Request {
+        let (body, stream) = match payload {
+            Payload::Buffered(body) => (body, None),
+            Payload::Stream(body) => (Bytes::new(), Some(BodyStream::new(body, max_body_size))),
+        };
+
         let mut request = Request {
             uri: parts.uri,
             method: parts.method,
@@ -36,11 +55,13 @@ impl Request {
             querys: Vec::new(),
             posts: Vec::new(),
             files: Vec::new(),
-            body
+            body,
+            stream,
+            max_body_size,
+            key: None
         };
 
         request.parse_query();
-        request.parse_post();
 
         request
     }
@@ -65,6 +86,11 @@ impl Request {
         &mut self.params
     }
 
+    #[inline]
+    pub fn get_params(&self) -> &HashMap<String, String> {
+        &self.params
+    }
+
     #[inline]
     pub fn query(&self, key: &str) -> Option<String> {
         self.querys
@@ -105,6 +131,55 @@ impl Request {
         &self.headers
     }
 
+    /// The cookies sent with the request, without attributes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/", |context| {
+    ///     let names: Vec<String> = context.request.cookies().iter().map(|c| c.name().to_owned()).collect();
+    ///     let theme = context.request.cookie("theme").unwrap_or_default();
+    ///
+    ///     format!("{} {}", names.join(","), theme)
+    /// });
+    ///
+    /// let response = app.test_client().get("/").header("Cookie", "theme=dark; lang=en").send();
+    ///
+    /// assert_eq!(response.get_body(), b"theme,lang dark");
+    /// ```
+    pub fn cookies(&self) -> Vec<Cookie> {
+        self.headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .flat_map(Cookie::parse_header)
+            .collect()
+    }
+
+    /// The value of a cookie, the first one if it was sent several times.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies()
+            .into_iter()
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value().to_owned())
+    }
+
+    /// The value of a cookie set with `Response::set_signed_cookie`, if its
+    /// signature is valid, see `App::secret_key`.
+    pub fn signed_cookie(&self, name: &str) -> Option<String> {
+        self.key.as_ref()?.verify(name, &self.cookie(name)?)
+    }
+
+    /// The value of a cookie set with `Response::set_private_cookie`, if it
+    /// decrypts, see `App::secret_key`.
+    pub fn private_cookie(&self, name: &str) -> Option<String> {
+        self.key.as_ref()?.decrypt(name, &self.cookie(name)?)
+    }
+
     #[inline]
     pub fn content_type(&self) -> Option<Mime> {
         if let Some(value) = self.headers.get(CONTENT_TYPE) {
@@ -128,11 +203,12 @@ impl Request {
         self.querys = url::from_str::<Vec<(String, String)>>(&url).unwrap_or_default();
     }
 
-    #[inline]
-    fn parse_post(&mut self) {
+    /// Parse the form fields and files of a buffered body, a malformed
+    /// multipart body fails with `Error::BadRequest`.
+    pub(crate) fn parse_post(&mut self) -> Result<()> {
         let content_type = match self.content_type() {
-            Some(c) => c.to_owned(),
-            None => return,
+            Some(c) if self.stream.is_none() => c,
+            _ => return Ok(()),
         };
 
         if content_type == mime::APPLICATION_WWW_FORM_URLENCODED {
@@ -141,13 +217,13 @@ impl Request {
         } else if content_type.type_() == mime::MULTIPART
             && content_type.subtype() == mime::FORM_DATA
         {
-            let form_data = self.parse_formdata();
-
-            if let Some(form_data) = form_data {
+            if let Some(form_data) = self.parse_formdata()? {
                 self.posts = form_data.fields;
                 self.files = form_data.files;
             }
         }
+
+        Ok(())
     }
 
     #[inline]
@@ -169,8 +245,45 @@ impl Request {
         &self.body
     }
 
+    /// The body size limit of the route, see `App::max_body_size`.
+    #[inline]
+    pub fn max_body_size(&self) -> Option<usize> {
+        self.max_body_size
+    }
+
+    /// Whether the body is streamed, see `Route::streaming`.
+    ///
+    /// A streaming request has an empty `body()`, and its form data is not
+    /// parsed.
+    #[inline]
+    pub fn is_streaming(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Take the streaming body as a blocking `Read`.
+    ///
+    /// Returns `None` if the route is not streaming, or the body was already
+    /// taken.
+    #[inline]
+    pub fn body_reader(&mut self) -> Option<BodyReader> {
+        self.stream.take().map(BodyReader::new)
+    }
+
+    /// Take the streaming body as an asynchronous `Stream` of chunks.
+    ///
+    /// Returns `None` if the route is not streaming, or the body was already
+    /// taken.
+    #[inline]
+    pub fn body_stream(&mut self) -> Option<BodyStream> {
+        self.stream.take()
+    }
+
+    /// Deserialize the json body, bad json fails with a `BindError`.
     #[inline]
     pub fn bind_json<D: DeserializeOwned>(&mut self) -> Result<D> {
-        Ok(serde_json::from_slice(&self.body())?)
+        let value = serde_json::from_slice(self.body())
+            .map_err(|err| BindError::new(Location::Body, err))?;
+
+        Ok(value)
     }
 }
@@ -1,33 +1,64 @@
-use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt;
 use std::fs::File;
 use std::io::Read;
+use std::result;
 
+use futures_core::Stream;
 use serde::Serialize;
 use serde_json;
 
 use hyper;
-//use hyper::header::ContentLength;
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{self, AsHeaderName, HeaderMap, HeaderName, HeaderValue};
+use tokio::task;
 
+use super::cookie::{Cookie, Key};
 use super::status_code::StatusCode;
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::util::executor::block_on;
+
+/// Size of the chunks read from a `Response::from_reader` reader.
+const CHUNK_SIZE: usize = 16 * 1024;
 
 #[derive(Debug)]
 pub struct Response {
     status_code: StatusCode,
-    headers: HashMap<String, String>,
-    body: Vec<u8>,
+    headers: HeaderMap,
+    cookies: Vec<Cookie>,
+    body: Body,
+    pub(crate) key: Option<Key>,
+}
+
+enum Body {
+    Data(Vec<u8>),
+    Stream(hyper::Body),
+    Reader(Box<dyn Read + Send>),
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Body::Data(ref data) => fmt.debug_tuple("Data").field(data).finish(),
+            Body::Stream(_) => fmt.write_str("Stream"),
+            Body::Reader(_) => fmt.write_str("Reader"),
+        }
+    }
 }
 
 impl Response {
     pub fn new(
         status_code: StatusCode,
-        headers: HashMap<String, String>,
+        headers: HeaderMap,
         data: Vec<u8>,
     ) -> Response {
         Response {
             status_code: status_code,
             headers: headers,
-            body: data,
+            cookies: Vec::new(),
+            body: Body::Data(data),
+            key: None,
         }
     }
 
@@ -35,7 +66,7 @@ impl Response {
     where
         S: Into<StatusCode>,
     {
-        Response::new(status_code.into(), HashMap::new(), Vec::new())
+        Response::new(status_code.into(), HeaderMap::new(), Vec::new())
     }
 
     pub fn from_data<C, D>(&mut self, content_type: C, data: D) -> Result<&mut Response>
@@ -45,24 +76,103 @@ impl Response {
     {
         let data = data.into();
 
-        self.headers
-            .insert("Content-Type".to_owned(), content_type.into());
-        self.body = data;
+        self.content_type(content_type)?;
+        self.body = Body::Data(data);
 
         Ok(self)
     }
 
-    pub fn from_file<C>(&mut self, content_type: C, mut file: File) -> Result<&mut Response>
+    /// Send a file, read chunk by chunk while it is sent.
+    ///
+    /// `Content-Length` is set from the file metadata.
+    pub fn from_file<C>(&mut self, content_type: C, file: File) -> Result<&mut Response>
     where
         C: Into<String>,
     {
-        //let file_size = file.metadata().ok().map(|v| v.len() as usize);
-        let mut data: Vec<u8> = Vec::new();
-        file.read_to_end(&mut data)?;
+        let length = file.metadata()?.len();
 
-        self.headers
-            .insert("Content-Type".to_owned(), content_type.into());
-        self.body = data;
+        self.from_reader(content_type, file)?;
+        self.headers.insert(header::CONTENT_LENGTH, length.into());
+
+        Ok(self)
+    }
+
+    /// Send the chunks of a stream as they come, with chunked transfer
+    /// encoding unless `Content-Length` is set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::Infallible;
+    ///
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/events", |context| {
+    ///     let (mut tx, rx) = tokio::sync::mpsc::channel::<Result<String, Infallible>>(4);
+    ///
+    ///     std::thread::spawn(move || {
+    ///         let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    ///
+    ///         runtime.block_on(async {
+    ///             for i in 0..3 {
+    ///                 tx.send(Ok(format!("event {}\n", i))).await.unwrap();
+    ///             }
+    ///         });
+    ///     });
+    ///
+    ///     context.response.from_stream("text/plain", rx).unwrap();
+    /// });
+    ///
+    /// let response = app.test_client().get("/events").send();
+    ///
+    /// assert_eq!(response.get_body(), b"event 0\nevent 1\nevent 2\n");
+    /// ```
+    pub fn from_stream<C, S, O, E>(&mut self, content_type: C, stream: S) -> Result<&mut Response>
+    where
+        C: Into<String>,
+        S: Stream<Item = std::result::Result<O, E>> + Send + 'static,
+        O: Into<Bytes> + 'static,
+        E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    {
+        self.content_type(content_type)?;
+        self.body = Body::Stream(hyper::Body::wrap_stream(stream));
+
+        Ok(self)
+    }
+
+    /// Send the content of a reader, read chunk by chunk on the blocking
+    /// pool while it is sent.
+    ///
+    /// A read error aborts the response.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    ///
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/export.csv", |context| {
+    ///     let csv = Cursor::new("id,name\n1,sincere\n");
+    ///
+    ///     context.response.from_reader("text/csv", csv).unwrap();
+    /// });
+    ///
+    /// let response = app.test_client().get("/export.csv").send();
+    ///
+    /// assert_eq!(response.get_body(), b"id,name\n1,sincere\n");
+    /// ```
+    pub fn from_reader<C, R>(&mut self, content_type: C, reader: R) -> Result<&mut Response>
+    where
+        C: Into<String>,
+        R: Read + Send + 'static,
+    {
+        self.content_type(content_type)?;
+        self.body = Body::Reader(Box::new(reader));
 
         Ok(self)
     }
@@ -74,10 +184,10 @@ impl Response {
         let string = string.into();
 
         self.headers.insert(
-            "Content-Type".to_owned(),
-            "text/plain; charset=UTF-8".to_owned(),
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; charset=UTF-8"),
         );
-        self.body = string.into();
+        self.body = Body::Data(string.into());
 
         Ok(self)
     }
@@ -89,10 +199,10 @@ impl Response {
         let string = string.into();
 
         self.headers.insert(
-            "Content-Type".to_owned(),
-            "text/html; charset=UTF-8".to_owned(),
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=UTF-8"),
         );
-        self.body = string.into();
+        self.body = Body::Data(string.into());
 
         Ok(self)
     }
@@ -101,14 +211,53 @@ impl Response {
         let data = serde_json::to_vec(&value)?;
 
         self.headers.insert(
-            "Content-Type".to_owned(),
-            "application/json; charset=UTF-8".to_owned(),
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json; charset=UTF-8"),
         );
-        self.body = data;
+        self.body = Body::Data(data);
 
         Ok(self)
     }
 
+    /// Describe an error, as the app does for failing handles when no
+    /// `App::on_error` hook is set.
+    ///
+    /// The status code is `Error::status_code`. Bind and validation errors
+    /// are sent as json, other client errors as text, and server errors
+    /// with their reason phrase only.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::Error;
+    /// use sincere::http::Response;
+    ///
+    /// let mut response = Response::empty(200);
+    ///
+    /// response.from_error(&Error::Error("Database is down".to_owned()));
+    ///
+    /// assert_eq!(response.get_status_code(), 500);
+    /// assert_eq!(response.get_body(), b"Internal Server Error");
+    /// ```
+    pub fn from_error(&mut self, err: &Error) -> &mut Response {
+        let code = err.status_code();
+
+        self.status_code(code);
+
+        let result = match *err {
+            Error::BindError(ref err) => self.from_json(err),
+            Error::ValidationError(ref err) => self.from_json(err),
+            _ if code < 500 => self.from_text(err.to_string()),
+            _ => self.from_text(StatusCode(code).default_reason_phrase()),
+        };
+
+        if result.is_err() {
+            self.from_text(StatusCode(code).default_reason_phrase()).ok();
+        }
+
+        self
+    }
+
     #[inline]
     pub fn status_code(&mut self, code: u16) -> &mut Response {
         self.status_code = code.into();
@@ -120,34 +269,371 @@ impl Response {
         self.status_code.0
     }
 
-    #[inline]
-    pub fn header<S>(&mut self, header: (S, S)) -> &mut Response
+    fn content_type<C: Into<String>>(&mut self, content_type: C) -> Result<()> {
+        let value = HeaderValue::try_from(content_type.into()).map_err(http::Error::from)?;
+
+        self.headers.insert(header::CONTENT_TYPE, value);
+
+        Ok(())
+    }
+
+    /// Set a header, replacing its previous values.
+    ///
+    /// Names and values may be strings or the typed `sincere::http::header`
+    /// constants and values, and fail if they are not valid in a header.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    /// use sincere::error::Result;
+    /// use sincere::http::header;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/", |context| -> Result<()> {
+    ///     context.response
+    ///         .header((header::CACHE_CONTROL, "no-cache"))?
+    ///         .header(("Vary", "Accept"))?
+    ///         .append_header(("Vary", "Accept-Encoding"))?
+    ///         .append_header(("Link", "</style.css>; rel=preload"))?
+    ///         .remove_header("Link");
+    ///
+    ///     assert!(context.response.header(("X-Bad", "line\nbreak")).is_err());
+    ///     assert!(context.response.header(("Bad Name", "value")).is_err());
+    ///
+    ///     Ok(())
+    /// });
+    ///
+    /// let response = app.test_client().get("/").send();
+    ///
+    /// assert_eq!(response.get_header("Cache-Control"), Some("no-cache"));
+    /// assert_eq!(response.get_header_all("Vary"), vec!["Accept", "Accept-Encoding"]);
+    /// assert_eq!(response.get_header("Link"), None);
+    /// ```
+    pub fn header<N, V>(&mut self, header: (N, V)) -> Result<&mut Response>
     where
-        S: Into<String>,
+        HeaderName: TryFrom<N>,
+        <HeaderName as TryFrom<N>>::Error: Into<http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+    {
+        let (name, value) = header_pair(header)?;
+
+        self.headers.insert(name, value);
+
+        Ok(self)
+    }
+
+    /// Add a value to a header, keeping its previous values.
+    pub fn append_header<N, V>(&mut self, header: (N, V)) -> Result<&mut Response>
+    where
+        HeaderName: TryFrom<N>,
+        <HeaderName as TryFrom<N>>::Error: Into<http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
     {
-        self.headers.insert(header.0.into(), header.1.into());
+        let (name, value) = header_pair(header)?;
+
+        self.headers.append(name, value);
+
+        Ok(self)
+    }
+
+    /// Remove all the values of a header.
+    pub fn remove_header<K: AsHeaderName>(&mut self, name: K) -> &mut Response {
+        self.headers.remove(name);
         self
     }
 
+    /// The first value of a header, if it is text.
     #[inline]
-    pub fn get_header(&self, name: &str) -> Option<&String> {
-        self.headers.get(name)
+    pub fn get_header<K: AsHeaderName>(&self, name: K) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// All the text values of a header.
+    pub fn get_header_all<K: AsHeaderName>(&self, name: K) -> Vec<&str> {
+        self.headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect()
     }
 
     #[inline]
-    pub fn get_headers(&self) -> &HashMap<String, String> {
+    pub fn get_headers(&self) -> &HeaderMap {
         &self.headers
     }
 
+    #[inline]
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    /// Set a cookie, replacing one of the same name, path and domain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    /// use sincere::http::cookie::Cookie;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/", |context| {
+    ///     context.response
+    ///         .set_cookie(Cookie::new("theme", "dark").path("/").http_only())
+    ///         .set_cookie(Cookie::new("lang", "en"))
+    ///         .remove_cookie(Cookie::new("session", "").path("/"));
+    /// });
+    ///
+    /// let response = app.test_client().get("/").send();
+    ///
+    /// let cookies: Vec<String> = response.get_cookies().iter().map(|cookie| cookie.to_string()).collect();
+    ///
+    /// assert_eq!(cookies, vec![
+    ///     "theme=dark; Path=/; HttpOnly",
+    ///     "lang=en",
+    ///     "session=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+    /// ]);
+    /// ```
+    pub fn set_cookie(&mut self, cookie: Cookie) -> &mut Response {
+        self.cookies.retain(|set| !set.same_as(&cookie));
+        self.cookies.push(cookie);
+        self
+    }
+
+    /// Remove a cookie from the browser, its path and domain must be the
+    /// ones it was set with.
+    pub fn remove_cookie(&mut self, cookie: Cookie) -> &mut Response {
+        self.set_cookie(cookie.into_removal())
+    }
+
+    /// Set a cookie signed with the app secret, read back with
+    /// `Request::signed_cookie`. Fails if the app has no secret key.
+    pub fn set_signed_cookie(&mut self, cookie: Cookie) -> Result<&mut Response> {
+        let cookie = cookie.into_signed(self.secret_key()?);
+        Ok(self.set_cookie(cookie))
+    }
+
+    /// Set a cookie encrypted with the app secret, read back with
+    /// `Request::private_cookie`. Fails if the app has no secret key.
+    pub fn set_private_cookie(&mut self, cookie: Cookie) -> Result<&mut Response> {
+        let cookie = cookie.into_private(self.secret_key()?);
+        Ok(self.set_cookie(cookie))
+    }
+
+    fn secret_key(&self) -> Result<&Key> {
+        self.key
+            .as_ref()
+            .ok_or_else(|| Error::Error("No secret key, see App::secret_key".to_owned()))
+    }
+
+    #[inline]
+    pub fn get_cookies(&self) -> &[Cookie] {
+        &self.cookies
+    }
+
+    pub fn get_cookie(&self, name: &str) -> Option<&Cookie> {
+        self.cookies.iter().find(|cookie| cookie.name() == name)
+    }
+
+    /// The body, empty if it is streamed.
+    #[inline]
+    pub fn get_body(&self) -> &[u8] {
+        match self.body {
+            Body::Data(ref data) => data,
+            _ => &[],
+        }
+    }
+
+    /// Whether the body is sent from a stream or a reader.
+    #[inline]
+    pub fn is_streaming(&self) -> bool {
+        !matches!(self.body, Body::Data(_))
+    }
+
+    /// Drop the body of a response to a `HEAD` request, `Content-Length`
+    /// still tells the length it would have if it is known.
+    pub(crate) fn strip_body(&mut self) {
+        if let Body::Data(ref data) = self.body {
+            if !self.headers.contains_key(header::CONTENT_LENGTH) {
+                self.headers.insert(header::CONTENT_LENGTH, data.len().into());
+            }
+        }
+
+        self.body = Body::Data(Vec::new());
+    }
+
+    /// Read a streamed body into memory, used by the test client.
+    pub(crate) async fn buffer(&mut self) -> Result<()> {
+        let mut body = match std::mem::replace(&mut self.body, Body::Data(Vec::new())) {
+            Body::Data(data) => {
+                self.body = Body::Data(data);
+                return Ok(());
+            }
+            Body::Stream(body) => body,
+            Body::Reader(reader) => reader_body(reader),
+        };
+
+        let mut data = Vec::new();
+
+        while let Some(chunk) = body.data().await {
+            data.extend_from_slice(&chunk?);
+        }
+
+        self.body = Body::Data(data);
+
+        Ok(())
+    }
+
+    /// Convert into a hyper response, must be called on the runtime.
+    ///
+    /// A status code out of the 100 to 999 range is sent as a 500.
     #[inline]
     pub(crate) fn raw_response(self) -> hyper::Response<hyper::Body> {
-        let mut header_builder = hyper::Response::builder()
-            .status(self.get_status_code());
+        let status = hyper::StatusCode::from_u16(self.get_status_code())
+            .unwrap_or(hyper::StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = match self.body {
+            Body::Data(data) => hyper::Body::from(data),
+            Body::Stream(body) => body,
+            Body::Reader(reader) => reader_body(reader),
+        };
 
-        for (key, value) in self.headers.iter() {
-            header_builder = header_builder.header(&**key, &**value);
+        let mut response = hyper::Response::new(body);
+
+        *response.status_mut() = status;
+        *response.headers_mut() = self.headers;
+
+        for cookie in self.cookies.iter() {
+            if let Ok(value) = HeaderValue::try_from(cookie.to_string()) {
+                response.headers_mut().append(header::SET_COOKIE, value);
+            }
         }
 
-        header_builder.body(hyper::Body::from(self.body)).unwrap()
+        response
     }
 }
+
+fn header_pair<N, V>(header: (N, V)) -> Result<(HeaderName, HeaderValue)>
+where
+    HeaderName: TryFrom<N>,
+    <HeaderName as TryFrom<N>>::Error: Into<http::Error>,
+    HeaderValue: TryFrom<V>,
+    <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+{
+    let name = HeaderName::try_from(header.0).map_err(Into::into)?;
+    let value = HeaderValue::try_from(header.1).map_err(Into::into)?;
+
+    Ok((name, value))
+}
+
+/// Value returned by a handle, written into the response.
+///
+/// # Examples
+///
+/// ```
+/// use serde::Deserialize;
+///
+/// use sincere::App;
+/// use sincere::error::Result;
+///
+/// #[derive(Deserialize)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// let mut app = App::new();
+///
+/// app.get("/", |_| "Hello world!");
+///
+/// app.post("/user", |context| -> Result<_> {
+///     let user: User = context.request.bind_json()?;
+///
+///     Ok((201, user.name))
+/// });
+///
+/// let client = app.test_client();
+///
+/// assert_eq!(client.get("/").send().get_body(), b"Hello world!");
+///
+/// let response = client.post("/user").body(r#"{"name": "sincere"}"#).send();
+/// assert_eq!(response.get_status_code(), 201);
+/// assert_eq!(response.get_body(), b"sincere");
+///
+/// assert_eq!(client.post("/user").body("{").send().get_status_code(), 400);
+/// ```
+pub trait IntoResponse {
+    fn into_response(self, response: &mut Response) -> Result<()>;
+}
+
+impl IntoResponse for () {
+    fn into_response(self, _response: &mut Response) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: IntoResponse, E: Into<Error>> IntoResponse for result::Result<T, E> {
+    fn into_response(self, response: &mut Response) -> Result<()> {
+        match self {
+            Ok(value) => value.into_response(response),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl IntoResponse for &'static str {
+    fn into_response(self, response: &mut Response) -> Result<()> {
+        response.from_text(self).map(|_| ())
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self, response: &mut Response) -> Result<()> {
+        response.from_text(self).map(|_| ())
+    }
+}
+
+impl IntoResponse for serde_json::Value {
+    fn into_response(self, response: &mut Response) -> Result<()> {
+        response.from_json(self).map(|_| ())
+    }
+}
+
+/// A status code and a value.
+impl<T: IntoResponse> IntoResponse for (u16, T) {
+    fn into_response(self, response: &mut Response) -> Result<()> {
+        response.status_code(self.0);
+        self.1.into_response(response)
+    }
+}
+
+/// Feed a hyper body from a reader on the blocking pool.
+fn reader_body(mut reader: Box<dyn Read + Send>) -> hyper::Body {
+    let (mut sender, body) = hyper::Body::channel();
+
+    task::spawn_blocking(move || {
+        let mut buf = vec![0; CHUNK_SIZE];
+
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => {
+                    let chunk = Bytes::copy_from_slice(&buf[..len]);
+
+                    if block_on(sender.send_data(chunk)).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => {
+                    sender.abort();
+                    break;
+                }
+            }
+        }
+    });
+
+    body
+}
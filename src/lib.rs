@@ -26,9 +26,7 @@
 //! fn main() {
 //!    let mut app = App::new();
 //!
-//!    app.get("/", |context| {
-//!        context.response.from_text("Hello world!").unwrap();
-//!    });
+//!    app.get("/", |_| "Hello world!");
 //!
 //!    //app.run("127.0.0.1:8000", 20).unwrap();
 //! }
@@ -44,7 +42,6 @@ extern crate httparse;
 extern crate num_cpus;
 extern crate rand;
 extern crate serde_json;
-extern crate twoway;
 extern crate url;
 extern crate hyper;
 extern crate queen_log;
@@ -55,8 +52,11 @@ pub extern crate nson;
 pub mod app;
 pub mod error;
 pub mod http;
+pub mod testing;
 pub mod text;
+pub mod session;
 pub mod util;
+pub mod validate;
 
 pub use self::app::App;
 pub use self::error::Error;
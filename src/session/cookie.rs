@@ -0,0 +1,121 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Duration, Utc};
+
+use super::{Data, SessionStore};
+use crate::error::{Error, Result};
+use crate::http::cookie::Key;
+
+/// Name the data is signed under, so that another signed value can't pass
+/// for a session.
+const SIGNING_NAME: &str = "sincere session";
+
+/// Browsers drop cookies above 4096 bytes, name and attributes included.
+const MAX_LEN: usize = 3800;
+
+/// Sessions kept in the cookie itself, signed so that the client can read
+/// but not change them, for deployments without shared state.
+///
+/// The data is not encrypted, don't put secrets in it. A session can't be
+/// revoked before it expires, a copy of an old cookie stays valid until
+/// then, and saving fails when the data outgrows a cookie.
+///
+/// # Examples
+///
+/// ```
+/// use sincere::App;
+/// use sincere::http::cookie::Key;
+/// use sincere::session::{CookieStore, Sessions};
+///
+/// let key = Key::derive(b"a session secret of at least 32 bytes, kept out of the repository");
+///
+/// let mut app = App::new();
+///
+/// app.middleware(Sessions::new(CookieStore::new(key, chrono::Duration::days(7))).middleware());
+/// ```
+pub struct CookieStore {
+    key: Key,
+    ttl: Duration,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    expires: i64,
+    data: Data,
+}
+
+impl CookieStore {
+    pub fn new(key: Key, ttl: Duration) -> CookieStore {
+        CookieStore { key, ttl }
+    }
+}
+
+impl SessionStore for CookieStore {
+    fn load(&self, id: &str) -> Result<Option<Data>> {
+        let entry = self
+            .key
+            .verify(SIGNING_NAME, id)
+            .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+            .and_then(|json| serde_json::from_slice::<Entry>(&json).ok());
+
+        match entry {
+            Some(entry) if entry.expires > Utc::now().timestamp() => Ok(Some(entry.data)),
+            _ => Ok(None),
+        }
+    }
+
+    fn save(&self, _id: Option<&str>, data: &Data) -> Result<String> {
+        let entry = Entry {
+            expires: (Utc::now() + self.ttl).timestamp(),
+            data: data.clone(),
+        };
+
+        let value = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&entry)?);
+        let signed = self.key.sign(SIGNING_NAME, &value);
+
+        if signed.len() > MAX_LEN {
+            return Err(Error::Error(format!(
+                "The session is too large for a cookie: {} bytes",
+                signed.len()
+            )));
+        }
+
+        Ok(signed)
+    }
+
+    fn destroy(&self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CookieStore;
+    use crate::http::cookie::Key;
+    use crate::session::{Data, SessionStore};
+
+    #[test]
+    fn signed() {
+        let store = CookieStore::new(Key::derive(&[7; 32]), chrono::Duration::hours(1));
+
+        let mut data = Data::new();
+        data.insert("user".to_owned(), "alice".into());
+
+        let value = store.save(None, &data).unwrap();
+        assert_eq!(store.load(&value).unwrap(), Some(data.clone()));
+
+        let other = CookieStore::new(Key::derive(&[8; 32]), chrono::Duration::hours(1));
+        assert_eq!(other.load(&value).unwrap(), None);
+
+        let mut tampered = value.clone();
+        tampered.push('A');
+        assert_eq!(store.load(&tampered).unwrap(), None);
+
+        let expired = CookieStore::new(Key::derive(&[7; 32]), chrono::Duration::seconds(-1));
+        let value = expired.save(None, &data).unwrap();
+        assert_eq!(store.load(&value).unwrap(), None);
+
+        data.insert("big".to_owned(), "x".repeat(4096).into());
+        assert!(store.save(None, &data).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use chrono::{Duration, Utc};
+
+use super::{generate_id, is_valid_id, Data, SessionStore};
+use crate::error::Result;
+
+/// Sessions kept as json files in a directory, one per session, which
+/// survive a restart and can be shared by the processes of a host.
+///
+/// A session expires once it has not been saved for the time to live. An
+/// expired session is removed when it is loaded, `sweep` removes all of
+/// them.
+pub struct FileStore {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    expires: i64,
+    data: Data,
+}
+
+impl FileStore {
+    /// The directory is created on the first save.
+    pub fn new<P: Into<PathBuf>>(dir: P, ttl: Duration) -> FileStore {
+        FileStore {
+            dir: dir.into(),
+            ttl,
+        }
+    }
+
+    /// Remove the expired sessions, and return how many there were.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::thread;
+    ///
+    /// use sincere::session::FileStore;
+    ///
+    /// let store = FileStore::new("/var/lib/app/sessions", chrono::Duration::days(1));
+    ///
+    /// thread::spawn(move || loop {
+    ///     store.sweep().unwrap();
+    ///     thread::sleep(std::time::Duration::from_secs(3600));
+    /// });
+    /// ```
+    pub fn sweep(&self) -> Result<usize> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut swept = 0;
+
+        for entry in entries {
+            let name = entry?.file_name();
+
+            if let Some(id) = name.to_str() {
+                if is_valid_id(id) && self.read(id)?.is_none() {
+                    swept += 1;
+                }
+            }
+        }
+
+        Ok(swept)
+    }
+
+    /// The data of a session, removing it if it expired. Files which can't
+    /// be read as a session are treated as expired.
+    fn read(&self, id: &str) -> Result<Option<Data>> {
+        let path = self.dir.join(id);
+
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        match serde_json::from_slice::<Entry>(&content) {
+            Ok(ref entry) if entry.expires > Utc::now().timestamp() => Ok(Some(entry.data.clone())),
+            _ => {
+                self.destroy(id)?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl SessionStore for FileStore {
+    fn load(&self, id: &str) -> Result<Option<Data>> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+
+        self.read(id)
+    }
+
+    fn save(&self, id: Option<&str>, data: &Data) -> Result<String> {
+        let id = match id {
+            Some(id) if is_valid_id(id) => id.to_owned(),
+            _ => generate_id(),
+        };
+
+        let entry = Entry {
+            expires: (Utc::now() + self.ttl).timestamp(),
+            data: data.clone(),
+        };
+
+        fs::create_dir_all(&self.dir)?;
+
+        // written aside then renamed, so that a session is never read half
+        // written
+        let temp = self.dir.join(format!(".{}.tmp", id));
+
+        fs::write(&temp, serde_json::to_vec(&entry)?)?;
+        fs::rename(&temp, self.dir.join(&id))?;
+
+        Ok(id)
+    }
+
+    fn destroy(&self, id: &str) -> Result<()> {
+        if !is_valid_id(id) {
+            return Ok(());
+        }
+
+        match fs::remove_file(self.dir.join(id)) {
+            Ok(()) => Ok(()),
+            Err(ref err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileStore;
+    use crate::session::{Data, SessionStore};
+
+    #[test]
+    fn files() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let store = FileStore::new(dir.path(), chrono::Duration::hours(1));
+
+        let mut data = Data::new();
+        data.insert("user".to_owned(), "alice".into());
+
+        let id = store.save(None, &data).unwrap();
+        assert_eq!(store.load(&id).unwrap(), Some(data.clone()));
+        assert_eq!(store.save(Some(&id), &data).unwrap(), id);
+
+        assert_eq!(store.load("../../etc/passwd").unwrap(), None);
+        assert_ne!(store.save(Some("../escape"), &data).unwrap(), "../escape");
+
+        let expired = FileStore::new(dir.path(), chrono::Duration::seconds(-1));
+        let old = expired.save(None, &data).unwrap();
+
+        assert_eq!(store.sweep().unwrap(), 1);
+        assert_eq!(store.load(&old).unwrap(), None);
+
+        store.destroy(&id).unwrap();
+        assert_eq!(store.load(&id).unwrap(), None);
+    }
+}
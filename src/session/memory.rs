@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{generate_id, Data, SessionStore};
+use crate::error::Result;
+
+/// Sessions kept in memory, lost on restart.
+///
+/// A session expires once it has not been saved for the time to live. The
+/// expired ones are evicted when they are loaded, and swept on a save once
+/// per time to live.
+pub struct MemoryStore {
+    ttl: Duration,
+    state: Mutex<State>,
+}
+
+struct State {
+    sessions: HashMap<String, (Data, Instant)>,
+    swept: Instant,
+}
+
+impl MemoryStore {
+    /// # Panics
+    ///
+    /// If the time to live is not positive.
+    pub fn new(ttl: chrono::Duration) -> MemoryStore {
+        let ttl = ttl.to_std().expect("The time to live must be positive");
+
+        MemoryStore {
+            ttl,
+            state: Mutex::new(State {
+                sessions: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+
+    /// The number of sessions held, including the expired ones not evicted
+    /// yet.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> Result<Option<Data>> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.sessions.get(id) {
+            Some((data, expires)) if *expires > Instant::now() => Ok(Some(data.clone())),
+            Some(_) => {
+                state.sessions.remove(id);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, id: Option<&str>, data: &Data) -> Result<String> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        if now.duration_since(state.swept) >= self.ttl {
+            state.sessions.retain(|_, (_, expires)| *expires > now);
+            state.swept = now;
+        }
+
+        let id = id.map(ToOwned::to_owned).unwrap_or_else(generate_id);
+
+        state.sessions.insert(id.clone(), (data.clone(), now + self.ttl));
+
+        Ok(id)
+    }
+
+    fn destroy(&self, id: &str) -> Result<()> {
+        self.state.lock().unwrap().sessions.remove(id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::MemoryStore;
+    use crate::session::{Data, SessionStore};
+
+    #[test]
+    fn expiry() {
+        let store = MemoryStore::new(chrono::Duration::milliseconds(50));
+
+        let mut data = Data::new();
+        data.insert("user".to_owned(), "alice".into());
+
+        let id = store.save(None, &data).unwrap();
+        assert_eq!(store.load(&id).unwrap(), Some(data.clone()));
+        assert_eq!(store.save(Some(&id), &data).unwrap(), id);
+
+        thread::sleep(std::time::Duration::from_millis(60));
+
+        let other = store.save(None, &data).unwrap();
+        assert_eq!(store.len(), 1);
+
+        assert_eq!(store.load(&id).unwrap(), None);
+        assert!(store.load(&other).unwrap().is_some());
+
+        store.destroy(&other).unwrap();
+        assert!(store.is_empty());
+    }
+}
@@ -0,0 +1,441 @@
+//! Server-side sessions.
+//!
+//! The [`Sessions`](struct.Sessions.html) middleware loads the session of a
+//! request from a [`SessionStore`](trait.SessionStore.html), by the id in its
+//! session cookie, and saves it back once the response is finished, if it
+//! changed. Handles reach it with `Context::session`.
+//!
+//! Three stores are provided: [`MemoryStore`](struct.MemoryStore.html) and
+//! [`FileStore`](struct.FileStore.html) keep the data on the server, while
+//! [`CookieStore`](struct.CookieStore.html) signs it into the cookie itself
+//! for stateless deployments.
+//!
+//! # Examples
+//!
+//! ```
+//! use sincere::App;
+//! use sincere::error::Result;
+//! use sincere::session::{MemoryStore, Sessions};
+//!
+//! let mut app = App::new();
+//!
+//! app.middleware(Sessions::new(MemoryStore::new(chrono::Duration::hours(1))).middleware());
+//!
+//! app.post("/login", |context| -> Result<()> {
+//!     let session = context.session()?;
+//!
+//!     // a new id after a privilege change, against session fixation
+//!     session.rotate();
+//!     session.set("user", &42)?;
+//!
+//!     Ok(())
+//! });
+//!
+//! app.get("/", |context| -> Result<String> {
+//!     let user: Option<u32> = context.session()?.get("user");
+//!
+//!     Ok(format!("{:?}", user))
+//! });
+//!
+//! let client = app.test_client();
+//!
+//! let response = client.post("/login").send();
+//! let id = response.get_cookie("sid").unwrap().value().to_owned();
+//!
+//! let response = client.get("/").header("Cookie", &format!("sid={}", id)).send();
+//! assert_eq!(response.get_body(), b"Some(42)");
+//!
+//! let response = client.get("/").send();
+//! assert_eq!(response.get_body(), b"None");
+//! ```
+use std::sync::Arc;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Duration;
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::app::context::Context;
+use crate::app::App;
+use crate::error::Result;
+use crate::http::cookie::{Cookie, SameSite};
+
+pub use self::cookie::CookieStore;
+pub use self::file::FileStore;
+pub use self::memory::MemoryStore;
+
+mod cookie;
+mod file;
+mod memory;
+
+/// The values of a session.
+pub type Data = serde_json::Map<String, Value>;
+
+/// Length of a session id, 32 random bytes encoded in base64.
+const ID_LEN: usize = 43;
+
+/// Where the sessions are kept.
+///
+/// The session cookie holds what `save` returns, and is handed back to
+/// `load` on the next request. For a server store it is an id, for
+/// `CookieStore` the signed data itself.
+pub trait SessionStore: Send + Sync + 'static {
+    /// The data of a session, `None` if it is unknown or expired.
+    fn load(&self, id: &str) -> Result<Option<Data>>;
+
+    /// Store the data of a session, under its id if it has one, and return
+    /// the value of the session cookie.
+    fn save(&self, id: Option<&str>, data: &Data) -> Result<String>;
+
+    /// Forget a session.
+    fn destroy(&self, id: &str) -> Result<()>;
+}
+
+/// A random session id, 43 url-safe characters.
+pub fn generate_id() -> String {
+    let mut id = [0; 32];
+    rand::thread_rng().fill_bytes(&mut id);
+
+    URL_SAFE_NO_PAD.encode(id)
+}
+
+/// Whether the id could have been made by `generate_id`, so that a cookie
+/// can't name anything else in a store.
+pub(crate) fn is_valid_id(id: &str) -> bool {
+    id.len() == ID_LEN
+        && id
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+}
+
+/// The session of a request.
+#[derive(Debug, Default)]
+pub struct Session {
+    id: Option<String>,
+    data: Data,
+    changed: bool,
+    rotated: bool,
+    destroyed: bool,
+}
+
+impl Session {
+    fn load(id: String, data: Data) -> Session {
+        Session {
+            id: Some(id),
+            data,
+            ..Session::default()
+        }
+    }
+
+    /// The id of the session, `None` until it is first saved.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// A value of the session, `None` if it is missing or of another type.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.data
+            .get(key)
+            .and_then(|value| T::deserialize(value).ok())
+    }
+
+    /// Set a value of the session, fails if it can't be serialized to json.
+    pub fn set<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<()> {
+        self.data.insert(key.to_owned(), serde_json::to_value(value)?);
+        self.changed = true;
+
+        Ok(())
+    }
+
+    /// Remove a value of the session, and return it.
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let value = self.data.remove(key);
+
+        if value.is_some() {
+            self.changed = true;
+        }
+
+        value
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.data.contains_key(key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Remove all the values of the session.
+    pub fn clear(&mut self) {
+        if !self.data.is_empty() {
+            self.data.clear();
+            self.changed = true;
+        }
+    }
+
+    /// Give the session a new id when it is saved, and destroy the old one.
+    ///
+    /// Call it when the privileges of the session change, such as on login
+    /// or logout, so that an id planted or seen before can't be used after.
+    pub fn rotate(&mut self) {
+        self.rotated = true;
+        self.changed = true;
+    }
+
+    /// Destroy the session, in the store and in the browser.
+    pub fn destroy(&mut self) {
+        self.data.clear();
+        self.destroyed = true;
+    }
+}
+
+/// The session middleware, with the settings of its cookie.
+///
+/// The cookie is named `sid`, has the path `/`, is `HttpOnly` and
+/// `SameSite=Lax`, and lasts as long as the browser session.
+#[derive(Clone)]
+pub struct Sessions {
+    store: Arc<dyn SessionStore>,
+    cookie_name: String,
+    path: String,
+    domain: Option<String>,
+    secure: bool,
+    same_site: SameSite,
+    max_age: Option<Duration>,
+}
+
+impl Sessions {
+    pub fn new<S: SessionStore>(store: S) -> Sessions {
+        Sessions {
+            store: Arc::new(store),
+            cookie_name: "sid".to_owned(),
+            path: "/".to_owned(),
+            domain: None,
+            secure: false,
+            same_site: SameSite::Lax,
+            max_age: None,
+        }
+    }
+
+    pub fn cookie_name(&mut self, name: &str) -> &mut Sessions {
+        self.cookie_name = name.to_owned();
+        self
+    }
+
+    pub fn path(&mut self, path: &str) -> &mut Sessions {
+        self.path = path.to_owned();
+        self
+    }
+
+    pub fn domain(&mut self, domain: &str) -> &mut Sessions {
+        self.domain = Some(domain.to_owned());
+        self
+    }
+
+    /// Send the cookie over https only.
+    pub fn secure(&mut self, secure: bool) -> &mut Sessions {
+        self.secure = secure;
+        self
+    }
+
+    pub fn same_site(&mut self, same_site: SameSite) -> &mut Sessions {
+        self.same_site = same_site;
+        self
+    }
+
+    /// Keep the cookie after the browser is closed, it should not outlive
+    /// the sessions of the store.
+    pub fn max_age(&mut self, max_age: Duration) -> &mut Sessions {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// The middleware, for `App::middleware`.
+    ///
+    /// The session is loaded in a `begin` handle and saved in a `finish`
+    /// handle, so it must be used before the handles which need it.
+    pub fn middleware(&self) -> impl Fn(&mut App) {
+        let sessions = Arc::new(self.clone());
+
+        move |app| {
+            let begin = sessions.clone();
+            let finish = sessions.clone();
+
+            app.begin(move |context| begin.load(context));
+            app.finish(move |context| finish.save(context));
+        }
+    }
+
+    fn load(&self, context: &mut Context) -> Result<()> {
+        let id = context.request.cookie(&self.cookie_name);
+
+        let session = match id {
+            Some(id) => match self.store.load(&id)? {
+                Some(data) => Session::load(id, data),
+                None => Session::default(),
+            },
+            None => Session::default(),
+        };
+
+        context.session = Some(session);
+
+        Ok(())
+    }
+
+    fn save(&self, context: &mut Context) -> Result<()> {
+        let session = match context.session.take() {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        if session.destroyed {
+            if let Some(ref id) = session.id {
+                self.store.destroy(id)?;
+            }
+
+            if session.id.is_some() || context.request.cookie(&self.cookie_name).is_some() {
+                context.response.remove_cookie(self.cookie(String::new()));
+            }
+
+            return Ok(());
+        }
+
+        if !session.changed {
+            return Ok(());
+        }
+
+        let id = match session.id {
+            Some(ref id) if session.rotated => {
+                self.store.destroy(id)?;
+                None
+            }
+            ref id => id.as_deref(),
+        };
+
+        let value = self.store.save(id, &session.data)?;
+
+        if session.id.as_deref() != Some(&*value) {
+            context.response.set_cookie(self.cookie(value));
+        }
+
+        Ok(())
+    }
+
+    fn cookie(&self, value: String) -> Cookie {
+        let mut cookie = Cookie::new(&*self.cookie_name, value)
+            .path(&*self.path)
+            .http_only()
+            .same_site(self.same_site);
+
+        if let Some(ref domain) = self.domain {
+            cookie = cookie.domain(&**domain);
+        }
+
+        if let Some(max_age) = self.max_age {
+            cookie = cookie.max_age(max_age);
+        }
+
+        if self.secure {
+            cookie = cookie.secure();
+        }
+
+        cookie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryStore, Sessions};
+    use crate::error::Result;
+    use crate::App;
+
+    fn app() -> App {
+        let mut app = App::new();
+
+        app.middleware(Sessions::new(MemoryStore::new(chrono::Duration::hours(1))).middleware());
+
+        app.post("/login", |context| -> Result<()> {
+            let session = context.session()?;
+            session.rotate();
+            session.set("user", "alice")
+        });
+
+        app.post("/visit", |context| -> Result<String> {
+            let session = context.session()?;
+            let visits = session.get::<u32>("visits").unwrap_or(0) + 1;
+            session.set("visits", &visits)?;
+
+            Ok(visits.to_string())
+        });
+
+        app.post("/logout", |context| -> Result<()> {
+            context.session()?.destroy();
+            Ok(())
+        });
+
+        app.get("/user", |context| -> Result<String> {
+            Ok(context.session()?.get::<String>("user").unwrap_or_default())
+        });
+
+        app
+    }
+
+    fn sid(response: &crate::http::Response) -> Option<String> {
+        response.get_cookie("sid").map(|cookie| cookie.value().to_owned())
+    }
+
+    #[test]
+    fn lifecycle() {
+        let client = app().test_client();
+
+        let response = client.get("/user").send();
+        assert_eq!(sid(&response), None);
+
+        let response = client.post("/visit").send();
+        let id = sid(&response).unwrap();
+        assert_eq!(response.get_body(), b"1");
+
+        let cookie = format!("sid={}", id);
+
+        let response = client.post("/visit").header("Cookie", &cookie).send();
+        assert_eq!(response.get_body(), b"2");
+        assert_eq!(sid(&response), None);
+
+        let response = client.post("/login").header("Cookie", &cookie).send();
+        let rotated = sid(&response).unwrap();
+        assert_ne!(rotated, id);
+
+        let response = client.get("/user").header("Cookie", &cookie).send();
+        assert_eq!(response.get_body(), b"");
+
+        let cookie = format!("sid={}", rotated);
+
+        let response = client.get("/user").header("Cookie", &cookie).send();
+        assert_eq!(response.get_body(), b"alice");
+
+        let response = client.post("/visit").header("Cookie", &cookie).send();
+        assert_eq!(response.get_body(), b"3");
+
+        let response = client.post("/logout").header("Cookie", &cookie).send();
+        assert_eq!(response.get_cookie("sid").unwrap().get_max_age(), Some(chrono::Duration::zero()));
+
+        let response = client.get("/user").header("Cookie", &cookie).send();
+        assert_eq!(response.get_body(), b"");
+    }
+
+    #[test]
+    fn not_installed() {
+        let mut app = App::new();
+
+        app.get("/", |context| -> Result<()> {
+            context.session()?;
+            Ok(())
+        });
+
+        assert_eq!(app.test_client().get("/").send().get_status_code(), 500);
+    }
+}
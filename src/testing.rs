@@ -0,0 +1,301 @@
+//! In-process test client.
+//!
+//! Requests are built here and driven straight through the app's routes and
+//! middlewares, no socket is bound and no server thread is needed.
+//!
+//! # Examples
+//!
+//! ```
+//! use sincere::App;
+//!
+//! let mut app = App::new();
+//!
+//! app.post("/echo", |context| {
+//!     let body = context.request.body().to_vec();
+//!     context.response.from_data("text/plain", body).unwrap();
+//! });
+//!
+//! let client = app.test_client();
+//!
+//! let response = client.post("/echo").body("hello").send();
+//!
+//! assert_eq!(response.get_status_code(), 200);
+//! assert_eq!(response.get_body(), b"hello");
+//! ```
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use serde_json;
+use tokio::runtime::{self, Runtime};
+
+use crate::app::App;
+use crate::http::{Method, Response};
+
+/// Test client, owns the app under test and a runtime to drive it.
+pub struct TestClient {
+    app: Arc<App>,
+    runtime: Mutex<Runtime>,
+}
+
+impl TestClient {
+    /// Create a test client for the app.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    /// use sincere::testing::TestClient;
+    ///
+    /// let client = TestClient::new(App::new());
+    ///
+    /// assert_eq!(client.get("/").send().get_status_code(), 404);
+    /// ```
+    pub fn new(mut app: App) -> TestClient {
+        app.build_router();
+
+        let runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .expect("Can't build the test runtime");
+
+        TestClient {
+            app: Arc::new(app),
+            runtime: Mutex::new(runtime),
+        }
+    }
+
+    /// Start building a request with the given method.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    /// use sincere::http::Method;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.add(Method::GET, "/", |context| {
+    ///     context.response.from_text("Get method!").unwrap();
+    /// });
+    ///
+    /// let client = app.test_client();
+    ///
+    /// let response = client.request(Method::GET, "/").send();
+    ///
+    /// assert_eq!(response.get_body(), b"Get method!");
+    /// ```
+    pub fn request(&self, method: Method, uri: &str) -> TestRequest<'_> {
+        TestRequest {
+            client: self,
+            builder: hyper::Request::builder().method(method).uri(uri),
+            body: Vec::new(),
+        }
+    }
+
+    /// Start building a GET request.
+    pub fn get(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::GET, uri)
+    }
+
+    /// Start building a POST request.
+    pub fn post(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::POST, uri)
+    }
+
+    /// Start building a PUT request.
+    pub fn put(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::PUT, uri)
+    }
+
+    /// Start building a PATCH request.
+    pub fn patch(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::PATCH, uri)
+    }
+
+    /// Start building a DELETE request.
+    pub fn delete(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::DELETE, uri)
+    }
+
+    /// Start building a HEAD request.
+    pub fn head(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::HEAD, uri)
+    }
+
+    /// Start building an OPTIONS request.
+    pub fn options(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::OPTIONS, uri)
+    }
+}
+
+/// Request builder returned by the `TestClient` methods.
+pub struct TestRequest<'a> {
+    client: &'a TestClient,
+    builder: http::request::Builder,
+    body: Vec<u8>,
+}
+
+impl<'a> TestRequest<'a> {
+    /// Add a request header.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.get("/", |context| {
+    ///     let token = context.request.header("Token").unwrap_or_default();
+    ///     context.response.from_text(token).unwrap();
+    /// });
+    ///
+    /// let client = app.test_client();
+    ///
+    /// let response = client.get("/").header("Token", "token").send();
+    ///
+    /// assert_eq!(response.get_body(), b"token");
+    /// ```
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.builder = self.builder.header(name, value);
+        self
+    }
+
+    /// Set the raw request body.
+    pub fn body<D: Into<Vec<u8>>>(mut self, data: D) -> Self {
+        self.body = data.into();
+        self
+    }
+
+    /// Set a json request body, `Content-Type` is set to `application/json`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    /// use serde_json::{json, Value};
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.post("/", |context| {
+    ///     let value: Value = context.request.bind_json().unwrap();
+    ///     context.response.from_json(value).unwrap();
+    /// });
+    ///
+    /// let client = app.test_client();
+    ///
+    /// let response = client.post("/").json(&json!({"hello": "world"})).send();
+    ///
+    /// assert_eq!(response.get_body(), br#"{"hello":"world"}"#);
+    /// ```
+    pub fn json<S: Serialize>(self, value: &S) -> Self {
+        let data = serde_json::to_vec(value).expect("Can't serialize json body");
+
+        self.header("Content-Type", "application/json").body(data)
+    }
+
+    /// Set an `application/x-www-form-urlencoded` request body.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sincere::App;
+    ///
+    /// let mut app = App::new();
+    ///
+    /// app.post("/", |context| {
+    ///     let name = context.request.post("name").unwrap_or_default();
+    ///     context.response.from_text(name).unwrap();
+    /// });
+    ///
+    /// let client = app.test_client();
+    ///
+    /// let response = client.post("/").form(&[("name", "sincere")]).send();
+    ///
+    /// assert_eq!(response.get_body(), b"sincere");
+    /// ```
+    pub fn form(self, fields: &[(&str, &str)]) -> Self {
+        let data = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(fields)
+            .finish();
+
+        self.header("Content-Type", "application/x-www-form-urlencoded")
+            .body(data)
+    }
+
+    /// Send the request through the app and return its response.
+    ///
+    /// A streamed response body is read into memory, so that `get_body`
+    /// returns it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the method, uri or a header of the request is not valid.
+    pub fn send(self) -> Response {
+        let request = self.builder.body(()).expect("Request is not valid");
+
+        let (parts, _) = request.into_parts();
+
+        let app = self.client.app.clone();
+        let body = hyper::Body::from(self.body);
+
+        self.client.runtime.lock().unwrap().block_on(async move {
+            let mut response = App::dispatch(app, parts, body).await;
+            response.buffer().await.expect("Can't read the response body");
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::App;
+
+    #[test]
+    fn route_params_and_query() {
+        let mut app = App::new();
+
+        app.get("/user/{id:[0-9]+}", |context| {
+            let id = context.request.param("id").unwrap();
+            let page = context.request.query("page").unwrap_or_default();
+            context.response.from_text(format!("{}:{}", id, page)).unwrap();
+        });
+
+        let client = app.test_client();
+
+        let response = client.get("/user/12?page=3").send();
+        assert_eq!(response.get_status_code(), 200);
+        assert_eq!(response.get_body(), b"12:3");
+
+        let response = client.get("/user/abc").send();
+        assert_eq!(response.get_status_code(), 404);
+    }
+
+    #[test]
+    fn middleware_stop() {
+        let mut app = App::new();
+
+        app.before(|context| {
+            if context.request.header("Token").is_none() {
+                context.response.status_code(401);
+                context.stop();
+            }
+        });
+
+        app.get("/", |context| {
+            context.response.from_text("Hello world!").unwrap();
+        });
+
+        let client = app.test_client();
+
+        assert_eq!(client.get("/").send().get_status_code(), 401);
+
+        let response = client.get("/").header("Token", "token").send();
+        assert_eq!(response.get_status_code(), 200);
+        assert_eq!(
+            response.get_header("Content-Type").unwrap(),
+            "text/plain; charset=UTF-8"
+        );
+    }
+}
@@ -2,5 +2,6 @@
 
 //mod message_queue;
 pub mod buf_read_ext;
+pub mod executor;
 pub mod thread_pool;
 pub mod url;
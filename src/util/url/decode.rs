@@ -1,21 +1,80 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::mem;
 
-use serde::de::value::MapDeserializer;
+use serde::de::value::{MapDeserializer, SeqDeserializer};
 use serde::de::{self, IntoDeserializer};
-use url::form_urlencoded::Parse;
+use url::form_urlencoded::parse;
 
 pub use serde::de::value::Error;
 
+/// Deepest bracket nesting of a key, further brackets are kept as is.
+const MAX_DEPTH: usize = 16;
+
+/// Decoder of urlencoded pairs.
+///
+/// Keys in bracket notation build nested values, `user[name]=x` a map
+/// and `tags[]=a&tags[]=b` a sequence, as do repeated keys, while `tags[]`
+/// without a value is an empty sequence. A sequence read as a single value
+/// gives its last element. Read as a sequence of
+/// pairs, the decoder gives the raw pairs in order.
+#[derive(Clone)]
 pub struct Decoder<'de> {
-    inner: MapDeserializer<'de, PartIterator<'de>, Error>,
+    pairs: Vec<(Cow<'de, str>, Option<Cow<'de, str>>)>,
+    literal: bool,
 }
 
 impl<'de> Decoder<'de> {
-    pub fn new(parser: Parse<'de>) -> Self {
+    /// Decode an urlencoded input, a key without `=` has no value.
+    pub fn new(input: &'de [u8]) -> Self {
+        let pairs = input
+            .split(|&byte| byte == b'&')
+            .filter_map(|piece| {
+                let (key, value) = parse(piece).next()?;
+
+                Some((key, Some(value).filter(|_| piece.contains(&b'='))))
+            })
+            .collect();
+
+        Decoder {
+            pairs,
+            literal: false,
+        }
+    }
+
+    /// Decode already split and percent-decoded pairs.
+    pub fn from_pairs<I>(pairs: I) -> Self
+    where
+        I: IntoIterator<Item = (Cow<'de, str>, Cow<'de, str>)>,
+    {
         Decoder {
-            inner: MapDeserializer::new(PartIterator(parser)),
+            pairs: pairs.into_iter().map(|(key, value)| (key, Some(value))).collect(),
+            literal: false,
         }
     }
+
+    /// Whether a key is in bracket notation.
+    pub fn has_brackets(&self) -> bool {
+        self.pairs.iter().any(|(key, _)| key.contains('['))
+    }
+
+    /// The decoder taking bracket keys as plain names, as a flat map of
+    /// `a[b]` keys needs.
+    pub fn literal(mut self) -> Self {
+        self.literal = true;
+        self
+    }
+
+    fn into_tree(self) -> Node<'de> {
+        let mut root = Node::Map(Entries::default());
+
+        for (key, value) in self.pairs {
+            let segments = if self.literal { vec![&*key] } else { split_key(&key) };
+            root = insert(Some(root), &segments, value);
+        }
+
+        root
+    }
 }
 
 impl<'de> de::Deserializer<'de> for Decoder<'de> {
@@ -32,21 +91,41 @@ impl<'de> de::Deserializer<'de> for Decoder<'de> {
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_map(self.inner)
+        self.into_tree().deserialize_map(visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_seq(self.inner)
+        let pairs = self
+            .pairs
+            .into_iter()
+            .map(|(k, v)| (Part(k), Part(v.unwrap_or_default())));
+
+        visitor.visit_seq(MapDeserializer::new(pairs))
     }
 
     fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        self.inner.end()?;
+        if !self.pairs.is_empty() {
+            return Err(de::Error::invalid_length(self.pairs.len(), &"empty"));
+        }
+
         visitor.visit_unit()
     }
 
@@ -71,7 +150,6 @@ impl<'de> de::Deserializer<'de> for Decoder<'de> {
         unit_struct
         newtype_struct
         tuple_struct
-        struct
         identifier
         tuple
         enum
@@ -79,13 +157,265 @@ impl<'de> de::Deserializer<'de> for Decoder<'de> {
     }
 }
 
-struct PartIterator<'de>(Parse<'de>);
+/// Split `a[b][]` into `a`, `b` and an empty segment.
+fn split_key(key: &str) -> Vec<&str> {
+    let open = match key.find('[') {
+        Some(open) if open > 0 => open,
+        _ => return vec![key],
+    };
+
+    let mut segments = vec![&key[..open]];
+    let mut rest = &key[open..];
+
+    while rest.starts_with('[') && segments.len() <= MAX_DEPTH {
+        match rest.find(']') {
+            Some(close) => {
+                segments.push(&rest[1..close]);
+                rest = &rest[close + 1..];
+            }
+            None => break,
+        }
+    }
+
+    if !rest.is_empty() {
+        segments.push(rest);
+    }
+
+    segments
+}
+
+/// Insert a value, the first value wins when a key is used both for a
+/// single value and nested values. A `key[]` without a value makes an
+/// empty sequence.
+fn insert<'de>(node: Option<Node<'de>>, segments: &[&str], value: Option<Cow<'de, str>>) -> Node<'de> {
+    let (segment, rest) = match segments.split_first() {
+        Some(first) => first,
+        None => {
+            let value = value.unwrap_or_default();
+
+            return match node {
+                None => Node::Value(value),
+                Some(Node::Value(first)) => Node::Seq(vec![Node::Value(first), Node::Value(value)]),
+                Some(Node::Seq(mut values)) => {
+                    values.push(Node::Value(value));
+                    Node::Seq(values)
+                }
+                Some(node) => node,
+            };
+        }
+    };
+
+    if segment.is_empty() {
+        if rest.is_empty() && value.is_none() {
+            return node.unwrap_or(Node::Seq(Vec::new()));
+        }
+
+        let value = insert(None, rest, value);
+
+        return match node {
+            None => Node::Seq(vec![value]),
+            Some(Node::Value(first)) => Node::Seq(vec![Node::Value(first), value]),
+            Some(Node::Seq(mut values)) => {
+                values.push(value);
+                Node::Seq(values)
+            }
+            Some(node) => node,
+        };
+    }
+
+    let mut entries = match node {
+        None => Entries::default(),
+        Some(Node::Map(entries)) => entries,
+        Some(node) => return node,
+    };
+
+    match entries.index.get(*segment) {
+        Some(&index) => {
+            let child = mem::replace(&mut entries.values[index].1, Node::Seq(Vec::new()));
+            entries.values[index].1 = insert(Some(child), rest, value);
+        }
+        None => {
+            entries.index.insert(segment.to_string(), entries.values.len());
+            entries.values.push((segment.to_string(), insert(None, rest, value)));
+        }
+    }
+
+    Node::Map(entries)
+}
+
+enum Node<'de> {
+    Value(Cow<'de, str>),
+    Seq(Vec<Node<'de>>),
+    Map(Entries<'de>),
+}
+
+#[derive(Default)]
+struct Entries<'de> {
+    index: HashMap<String, usize>,
+    values: Vec<(String, Node<'de>)>,
+}
+
+impl<'de> Node<'de> {
+    /// The node read as a single value, the last one of a sequence.
+    fn single(self) -> Node<'de> {
+        match self {
+            Node::Seq(values) => match values.into_iter().last() {
+                Some(last) => last.single(),
+                None => Node::Value(Cow::Borrowed("")),
+            },
+            node => node,
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de> for Node<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+macro_rules! forward_single_value {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+                where V: de::Visitor<'de>
+            {
+                match self.single() {
+                    Node::Value(value) => Part(value).$method(visitor),
+                    node => node.deserialize_any(visitor)
+                }
+            }
+        )*
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Node<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            Node::Value(value) => Part(value).deserialize_any(visitor),
+            Node::Seq(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            Node::Map(entries) => {
+                let entries = entries
+                    .values
+                    .into_iter()
+                    .map(|(key, value)| (Part(Cow::Owned(key)), value));
+
+                visitor.visit_map(MapDeserializer::new(entries))
+            }
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
 
-impl<'de> Iterator for PartIterator<'de> {
-    type Item = (Part<'de>, Part<'de>);
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.single() {
+            Node::Value(value) => Part(value).deserialize_enum(name, variants, visitor),
+            node => node.deserialize_any(visitor),
+        }
+    }
+
+    /// A single value is a sequence of one, a map with numeric keys a
+    /// sequence in the order of its keys.
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            Node::Value(value) => {
+                visitor.visit_seq(SeqDeserializer::new(Some(Node::Value(value)).into_iter()))
+            }
+            Node::Seq(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            Node::Map(entries) => {
+                let mut values = entries.values;
+
+                if values.iter().all(|(key, _)| key.parse::<usize>().is_ok()) {
+                    values.sort_by_key(|(key, _)| key.parse::<usize>().unwrap_or_default());
+                }
+
+                let values = values.into_iter().map(|(_, value)| value);
+
+                visitor.visit_seq(SeqDeserializer::new(values))
+            }
+        }
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_single_value! {
+        deserialize_bool
+        deserialize_u8
+        deserialize_u16
+        deserialize_u32
+        deserialize_u64
+        deserialize_i8
+        deserialize_i16
+        deserialize_i32
+        deserialize_i64
+        deserialize_f32
+        deserialize_f64
+        deserialize_char
+        deserialize_str
+        deserialize_string
+        deserialize_bytes
+        deserialize_byte_buf
+        deserialize_unit
+        deserialize_identifier
+    }
+
+    /// An empty sequence is an empty map, as `meta[]` without a value.
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self {
+            Node::Seq(ref values) if values.is_empty() => {
+                visitor.visit_map(MapDeserializer::new(std::iter::empty::<(Part, Node)>()))
+            }
+            node => node.deserialize_any(visitor),
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.0.next().map(|(k, v)| (Part(k), Part(v)))
+    forward_to_deserialize_any! {
+        unit_struct
+        tuple_struct
+        struct
+        ignored_any
     }
 }
 
@@ -131,6 +461,18 @@ impl<'de> de::Deserializer<'de> for Part<'de> {
         visitor.visit_some(self)
     }
 
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.0.into_deserializer().deserialize_enum(name, variants, visitor)
+    }
+
     forward_to_deserialize_any! {
         char
         str
@@ -144,7 +486,6 @@ impl<'de> de::Deserializer<'de> for Part<'de> {
         struct
         identifier
         tuple
-        enum
         ignored_any
         seq
         map
@@ -164,3 +505,132 @@ impl<'de> de::Deserializer<'de> for Part<'de> {
         f64 => deserialize_f64,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::split_key;
+    use crate::util::url::from_str;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Role {
+        Admin,
+        Guest,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Address {
+        city: String,
+        zip: Option<u32>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct User {
+        name: String,
+        role: Role,
+        address: Address,
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Item {
+        id: u32,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Form {
+        user: User,
+        items: Vec<Item>,
+        ids: Vec<u32>,
+        page: u32,
+    }
+
+    #[test]
+    fn nested() {
+        let form: Form = from_str(
+            "user[name]=sincere&user[role]=admin&user[address][city]=Paris\
+             &user[tags][]=a&user[tags][]=b\
+             &items[1][id]=2&items[0][id]=1\
+             &ids=1&ids=2&ids=3&page=1&page=2",
+        )
+        .unwrap();
+
+        assert_eq!(
+            form,
+            Form {
+                user: User {
+                    name: "sincere".to_owned(),
+                    role: Role::Admin,
+                    address: Address {
+                        city: "Paris".to_owned(),
+                        zip: None,
+                    },
+                    tags: vec!["a".to_owned(), "b".to_owned()],
+                },
+                items: vec![Item { id: 1 }, Item { id: 2 }],
+                ids: vec![1, 2, 3],
+                page: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn single_values() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Query {
+            tags: Vec<String>,
+            role: Option<Role>,
+        }
+
+        let query: Query = from_str("tags=a&role=guest").unwrap();
+        assert_eq!(query.tags, vec!["a".to_owned()]);
+        assert_eq!(query.role, Some(Role::Guest));
+
+        assert!(from_str::<Query>("tags=a&role=root").is_err());
+    }
+
+    #[test]
+    fn flat() {
+        let pairs: Vec<(String, String)> = from_str("a[b]=1&a=2&a=3").unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                ("a[b]".to_owned(), "1".to_owned()),
+                ("a".to_owned(), "2".to_owned()),
+                ("a".to_owned(), "3".to_owned())
+            ]
+        );
+
+        let map: HashMap<String, String> = from_str("a=1&b=2&a=3").unwrap();
+        assert_eq!(map["a"], "3");
+        assert_eq!(map["b"], "2");
+
+        let map: HashMap<String, String> = from_str("a[b]=1&c[]=2&c[]=3&d=4").unwrap();
+        assert_eq!(map["a[b]"], "1");
+        assert_eq!(map["c[]"], "3");
+        assert_eq!(map["d"], "4");
+
+        assert!(from_str::<HashMap<String, u32>>("a[b]=x").is_err());
+
+        assert!(from_str::<()>("").is_ok());
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(split_key("a"), vec!["a"]);
+        assert_eq!(split_key("a[b][]"), vec!["a", "b", ""]);
+        assert_eq!(split_key("[a]"), vec!["[a]"]);
+        assert_eq!(split_key("a[b"), vec!["a", "[b"]);
+        assert_eq!(split_key("a[b]c"), vec!["a", "b", "c"]);
+
+        let deep = format!("a{}", "[b]".repeat(10_000));
+        assert_eq!(split_key(&deep).len(), 18);
+
+        let value: HashMap<String, serde_json::Value> = from_str(&format!("{}=1", deep)).unwrap();
+        assert!(value.contains_key("a"));
+    }
+}
@@ -0,0 +1,755 @@
+use serde::ser::{self, Impossible, Serialize};
+
+pub use serde::de::value::Error;
+
+/// Encoder of urlencoded pairs.
+///
+/// The top level value is a struct, a map or a sequence of pairs. Nested
+/// values use the bracket notation of the decoder, `user[name]=x` for
+/// structs and maps, `tags[]=a` for sequences of single values and
+/// `items[0][id]=1` for sequences of structs. An empty sequence or map is
+/// its key alone, `tags[]` without a value. `None` and unit values are left
+/// out.
+pub struct Encoder<'a> {
+    pairs: &'a mut Vec<(String, Option<String>)>,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(pairs: &'a mut Vec<(String, Option<String>)>) -> Self {
+        Encoder { pairs }
+    }
+}
+
+fn unsupported<T>(what: &str) -> Result<T, Error> {
+    Err(ser::Error::custom(format!("Unsupported value: {}", what)))
+}
+
+macro_rules! unsupported_values {
+    ($($method:ident($($ty:ty),*);)*) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> Result<Self::Ok, Self::Error> {
+                unsupported(&stringify!($method)["serialize_".len()..])
+            }
+        )*
+    }
+}
+
+macro_rules! unsupported_compounds {
+    () => {
+        fn serialize_newtype_variant<T: ?Sized + Serialize>(
+            self,
+            _name: &'static str,
+            _index: u32,
+            _variant: &'static str,
+            _value: &T,
+        ) -> Result<Self::Ok, Self::Error> {
+            unsupported("enum variant")
+        }
+
+        fn serialize_tuple_variant(
+            self,
+            _name: &'static str,
+            _index: u32,
+            _variant: &'static str,
+            _len: usize,
+        ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+            unsupported("enum variant")
+        }
+
+        fn serialize_struct_variant(
+            self,
+            _name: &'static str,
+            _index: u32,
+            _variant: &'static str,
+            _len: usize,
+        ) -> Result<Self::SerializeStructVariant, Self::Error> {
+            unsupported("enum variant")
+        }
+    };
+}
+
+impl<'a> ser::Serializer for Encoder<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    unsupported_values! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_char(char);
+        serialize_str(&str);
+        serialize_bytes(&[u8]);
+        serialize_unit_variant(&'static str, u32, &'static str);
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(Compound::new(self.pairs, None))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Compound<'a>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(Compound::new(self.pairs, None))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound<'a>, Error> {
+        Ok(Compound::new(self.pairs, None))
+    }
+
+    unsupported_compounds!();
+}
+
+/// Fields, entries or elements of a value, `prefix` is `None` at the top
+/// level.
+pub struct Compound<'a> {
+    pairs: &'a mut Vec<(String, Option<String>)>,
+    prefix: Option<String>,
+    index: usize,
+    key: Option<String>,
+}
+
+impl<'a> Compound<'a> {
+    fn new(pairs: &'a mut Vec<(String, Option<String>)>, prefix: Option<String>) -> Self {
+        Compound {
+            pairs,
+            prefix,
+            index: 0,
+            key: None,
+        }
+    }
+
+    fn field<T: ?Sized + Serialize>(&mut self, name: &str, value: &T) -> Result<(), Error> {
+        let key = match self.prefix {
+            Some(ref prefix) => format!("{}[{}]", prefix, name),
+            None => name.to_owned(),
+        };
+
+        value.serialize(Value {
+            pairs: self.pairs,
+            key,
+            element: None,
+        })
+    }
+
+    /// End a sequence or map, an empty one is marked by its key alone, as
+    /// it would leave no pair otherwise.
+    fn finish(self) -> Result<(), Error> {
+        if let (0, Some(prefix)) = (self.index, self.prefix) {
+            self.pairs.push((format!("{}[]", prefix), None));
+        }
+
+        Ok(())
+    }
+
+    fn element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let index = self.index;
+        self.index += 1;
+
+        match self.prefix {
+            Some(ref prefix) => value.serialize(Value {
+                pairs: self.pairs,
+                key: prefix.clone(),
+                element: Some(index),
+            }),
+            None => value.serialize(Pair {
+                pairs: self.pairs,
+                key: None,
+            }),
+        }
+    }
+}
+
+impl<'a> ser::SerializeSeq for Compound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTuple for Compound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for Compound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeMap for Compound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(Key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.index += 1;
+
+        match self.key.take() {
+            Some(key) => self.field(&key, value),
+            None => unsupported("map value without a key"),
+        }
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStruct for Compound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(name, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A value under `key`, or an element of the sequence `key`.
+struct Value<'a> {
+    pairs: &'a mut Vec<(String, Option<String>)>,
+    key: String,
+    element: Option<usize>,
+}
+
+impl<'a> Value<'a> {
+    fn push<V: ToString>(self, value: V) -> Result<(), Error> {
+        let key = match self.element {
+            Some(_) => format!("{}[]", self.key),
+            None => self.key,
+        };
+
+        self.pairs.push((key, Some(value.to_string())));
+        Ok(())
+    }
+
+    fn compound(self) -> Compound<'a> {
+        let prefix = match self.element {
+            Some(index) => format!("{}[{}]", self.key, index),
+            None => self.key,
+        };
+
+        Compound::new(self.pairs, Some(prefix))
+    }
+}
+
+macro_rules! push_values {
+    ($($method:ident($ty:ty);)*) => {
+        $(
+            fn $method(self, value: $ty) -> Result<(), Error> {
+                self.push(value)
+            }
+        )*
+    }
+}
+
+impl<'a> ser::Serializer for Value<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    push_values! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_char(char);
+        serialize_str(&str);
+    }
+
+    unsupported_values! {
+        serialize_bytes(&[u8]);
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        self.push(variant)
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(self.compound())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Compound<'a>, Error> {
+        Ok(self.compound())
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Compound<'a>, Error> {
+        Ok(self.compound())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(self.compound())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound<'a>, Error> {
+        Ok(self.compound())
+    }
+
+    unsupported_compounds!();
+}
+
+/// A `(key, value)` element of a top level sequence.
+struct Pair<'a> {
+    pairs: &'a mut Vec<(String, Option<String>)>,
+    key: Option<String>,
+}
+
+impl<'a> ser::SerializeTuple for Pair<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        match self.key.take() {
+            None => {
+                self.key = Some(value.serialize(Key)?);
+                Ok(())
+            }
+            Some(key) => value.serialize(Value {
+                pairs: self.pairs,
+                key,
+                element: None,
+            }),
+        }
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for Pair<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Pair<'a>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Impossible<(), Error>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_tuple(self, len: usize) -> Result<Pair<'a>, Error> {
+        if len != 2 {
+            return unsupported("tuple which is not a pair");
+        }
+
+        Ok(self)
+    }
+
+    unsupported_values! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_char(char);
+        serialize_str(&str);
+        serialize_bytes(&[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(&'static str);
+        serialize_unit_variant(&'static str, u32, &'static str);
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<(), Error> {
+        unsupported("Option")
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        unsupported("sequence in a sequence of pairs")
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        unsupported("tuple struct in a sequence of pairs")
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        unsupported("map in a sequence of pairs")
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Error> {
+        unsupported("struct in a sequence of pairs")
+    }
+
+    unsupported_compounds!();
+}
+
+/// A map key or the key of a pair.
+struct Key;
+
+macro_rules! key_values {
+    ($($method:ident($ty:ty);)*) => {
+        $(
+            fn $method(self, value: $ty) -> Result<String, Error> {
+                Ok(value.to_string())
+            }
+        )*
+    }
+}
+
+impl ser::Serializer for Key {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    key_values! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_char(char);
+        serialize_str(&str);
+    }
+
+    unsupported_values! {
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_bytes(&[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(&'static str);
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<String, Error> {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<String, Error> {
+        unsupported("Option key")
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        unsupported("sequence key")
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        unsupported("tuple key")
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        unsupported("tuple struct key")
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        unsupported("map key")
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Error> {
+        unsupported("struct key")
+    }
+
+    unsupported_compounds!();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::util::url::{from_str, to_string};
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Role {
+        Admin,
+        Guest,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Item {
+        id: u32,
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Form {
+        name: String,
+        role: Role,
+        page: Option<u32>,
+        cursor: Option<String>,
+        ratio: f64,
+        ids: Vec<u32>,
+        items: Vec<Item>,
+        meta: BTreeMap<String, String>,
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut meta = BTreeMap::new();
+        meta.insert("a b".to_owned(), "&=?".to_owned());
+
+        let form = Form {
+            name: "你好 sincere".to_owned(),
+            role: Role::Admin,
+            page: Some(2),
+            cursor: None,
+            ratio: 0.5,
+            ids: vec![1, 2],
+            items: vec![
+                Item {
+                    id: 1,
+                    tags: vec!["x".to_owned()],
+                },
+                Item {
+                    id: 2,
+                    tags: vec!["y".to_owned(), "z".to_owned()],
+                },
+            ],
+            meta,
+        };
+
+        let encoded = to_string(&form).unwrap();
+
+        assert_eq!(
+            encoded,
+            "name=%E4%BD%A0%E5%A5%BD+sincere&role=admin&page=2&ratio=0.5&ids%5B%5D=1&ids%5B%5D=2\
+             &items%5B0%5D%5Bid%5D=1&items%5B0%5D%5Btags%5D%5B%5D=x\
+             &items%5B1%5D%5Bid%5D=2&items%5B1%5D%5Btags%5D%5B%5D=y&items%5B1%5D%5Btags%5D%5B%5D=z\
+             &meta%5Ba+b%5D=%26%3D%3F"
+        );
+
+        assert_eq!(from_str::<Form>(&encoded).unwrap(), form);
+    }
+
+    #[test]
+    fn empty_seq() {
+        let form = Form {
+            name: String::new(),
+            role: Role::Guest,
+            page: None,
+            cursor: None,
+            ratio: 1.0,
+            ids: vec![],
+            items: vec![Item { id: 1, tags: vec![] }],
+            meta: BTreeMap::new(),
+        };
+
+        let encoded = to_string(&form).unwrap();
+
+        assert_eq!(
+            encoded,
+            "name=&role=guest&ratio=1&ids%5B%5D&items%5B0%5D%5Bid%5D=1&items%5B0%5D%5Btags%5D%5B%5D\
+             &meta%5B%5D"
+        );
+
+        assert_eq!(from_str::<Form>(&encoded).unwrap(), form);
+
+        let ids: Option<Vec<u32>> = Some(vec![]);
+        assert_eq!(to_string(&[("ids", ids)]).unwrap(), "ids%5B%5D");
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Tags {
+            tags: Vec<String>,
+        }
+
+        assert_eq!(from_str::<Tags>("tags[]=").unwrap().tags, vec![""]);
+        assert!(from_str::<Tags>("tags[]").unwrap().tags.is_empty());
+    }
+
+    #[test]
+    fn pairs() {
+        let pairs = vec![("a", "1"), ("a", "2"), ("b[c]", "3")];
+
+        let encoded = to_string(&pairs).unwrap();
+        assert_eq!(encoded, "a=1&a=2&b%5Bc%5D=3");
+
+        let decoded: Vec<(String, String)> = from_str(&encoded).unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[2], ("b[c]".to_owned(), "3".to_owned()));
+
+        let mut map = BTreeMap::new();
+        map.insert(1, Some(true));
+        map.insert(2, None);
+
+        assert_eq!(to_string(&map).unwrap(), "1=true");
+        assert_eq!(to_string(&()).unwrap(), "");
+        assert_eq!(to_string(&None::<Item>).unwrap(), "");
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(to_string(&1).is_err());
+        assert!(to_string(&"a").is_err());
+        assert!(to_string(&vec![1, 2]).is_err());
+        assert!(to_string(&vec![(1, 2, 3)]).is_err());
+
+        #[derive(Serialize)]
+        enum Shape {
+            Square(u32),
+        }
+
+        #[derive(Serialize)]
+        struct Wrapper {
+            shape: Shape,
+        }
+
+        assert!(to_string(&Wrapper { shape: Shape::Square(1) }).is_err());
+    }
+}
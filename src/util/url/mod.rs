@@ -1,19 +1,149 @@
+use std::borrow::Cow;
+
 pub use serde::de;
 pub use serde::de::value::Error;
+pub use serde::ser;
 pub use url::form_urlencoded::parse;
 
 mod decode;
+mod encode;
 
 pub fn from_bytes<'de, T>(input: &'de [u8]) -> Result<T, Error>
 where
     T: de::Deserialize<'de>,
 {
-    T::deserialize(decode::Decoder::new(parse(input)))
+    decode(decode::Decoder::new(input))
+}
+
+/// Decode nested values, or a flat map of bracket keys taken as plain
+/// names if the nested values don't fit.
+fn decode<'de, T>(decoder: decode::Decoder<'de>) -> Result<T, Error>
+where
+    T: de::Deserialize<'de>,
+{
+    if !decoder.has_brackets() {
+        return T::deserialize(decoder);
+    }
+
+    let literal = decoder.clone().literal();
+
+    T::deserialize(decoder).or_else(|err| T::deserialize(literal).map_err(|_| err))
+}
+
+/// Deserialize from already split and percent-decoded pairs.
+///
+/// # Examples
+///
+/// ```
+/// use sincere::util::url;
+///
+/// let pairs = vec![("id".to_owned(), "12".to_owned())];
+///
+/// let value: Vec<(String, u32)> = url::from_pairs(&pairs).unwrap();
+///
+/// assert_eq!(value, vec![("id".to_owned(), 12)]);
+/// ```
+pub fn from_pairs<'de, T, I, K, V>(pairs: I) -> Result<T, Error>
+where
+    T: de::Deserialize<'de>,
+    I: IntoIterator<Item = &'de (K, V)>,
+    K: AsRef<str> + 'de,
+    V: AsRef<str> + 'de,
+{
+    let pairs = pairs
+        .into_iter()
+        .map(|(k, v)| (Cow::Borrowed(k.as_ref()), Cow::Borrowed(v.as_ref())));
+
+    decode(decode::Decoder::from_pairs(pairs))
 }
 
+/// Deserialize an urlencoded string.
+///
+/// Bracket keys and repeated keys build nested values, a single value
+/// takes the last of repeated keys. Bracket keys are taken as plain names
+/// when decoding into a flat map of single values.
+///
+/// # Examples
+///
+/// ```
+/// use serde::Deserialize;
+///
+/// use sincere::util::url;
+///
+/// #[derive(Deserialize)]
+/// struct User {
+///     name: String,
+///     tags: Vec<String>,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Form {
+///     user: User,
+///     ids: Vec<u32>,
+/// }
+///
+/// let form: Form = url::from_str("user[name]=sincere&user[tags][]=a&user[tags][]=b&ids=1&ids=2").unwrap();
+///
+/// assert_eq!(form.user.name, "sincere");
+/// assert_eq!(form.user.tags, vec!["a", "b"]);
+/// assert_eq!(form.ids, vec![1, 2]);
+/// ```
 pub fn from_str<'de, T>(input: &'de str) -> Result<T, Error>
 where
     T: de::Deserialize<'de>,
 {
     from_bytes(input.as_bytes())
 }
+
+/// Serialize to an urlencoded string, in the notation `from_str` reads.
+///
+/// # Examples
+///
+/// ```
+/// use serde::Serialize;
+///
+/// use sincere::util::url;
+///
+/// #[derive(Serialize)]
+/// struct Item {
+///     id: u32,
+/// }
+///
+/// #[derive(Serialize)]
+/// struct Query {
+///     q: String,
+///     page: Option<u32>,
+///     tags: Vec<String>,
+///     items: Vec<Item>,
+/// }
+///
+/// let query = Query {
+///     q: "a b".to_owned(),
+///     page: None,
+///     tags: vec!["x".to_owned()],
+///     items: vec![Item { id: 1 }],
+/// };
+///
+/// assert_eq!(url::to_string(&query).unwrap(), "q=a+b&tags%5B%5D=x&items%5B0%5D%5Bid%5D=1");
+///
+/// assert_eq!(url::to_string(&[("a", 1), ("a", 2)]).unwrap(), "a=1&a=2");
+/// ```
+pub fn to_string<T>(value: &T) -> Result<String, Error>
+where
+    T: ser::Serialize + ?Sized,
+{
+    let mut pairs = Vec::new();
+
+    value.serialize(encode::Encoder::new(&mut pairs))?;
+
+    let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+
+    for (key, value) in pairs {
+        match value {
+            Some(value) => serializer.append_pair(&key, &value),
+            None => serializer.append_key_only(&key),
+        };
+    }
+
+    Ok(serializer.finish())
+}
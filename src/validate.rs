@@ -0,0 +1,623 @@
+//! Validation of bound request values.
+//!
+//! A type implements [`Validate`](trait.Validate.html) by declaring rules
+//! for its fields on a [`Validator`](struct.Validator.html). The
+//! `Request::valid_*` binders deserialize and validate in one step, and
+//! fail with a [`Rejection`](enum.Rejection.html) that is sent back as
+//! json, a 400 if the value could not be bound and a 422 listing each
+//! failing field if it is invalid.
+//!
+//! # Examples
+//!
+//! ```
+//! use serde::Deserialize;
+//!
+//! use sincere::App;
+//! use sincere::validate::{Validate, Validator};
+//!
+//! #[derive(Deserialize)]
+//! struct User {
+//!     name: String,
+//!     email: Option<String>,
+//!     age: u8,
+//! }
+//!
+//! impl Validate for User {
+//!     fn rules(&self, validator: &mut Validator) {
+//!         validator.field("name", &self.name).required().length(2, 20);
+//!         validator.optional("email", &self.email).email();
+//!         validator.field("age", &self.age).range(18, 130);
+//!     }
+//! }
+//!
+//! let mut app = App::new();
+//!
+//! app.post("/user", |context| {
+//!     match context.request.valid_json::<User>() {
+//!         Ok(user) => {
+//!             context.response.from_text(user.name).unwrap();
+//!         }
+//!         Err(err) => {
+//!             context.response.status_code(err.status_code()).from_json(&err).unwrap();
+//!         }
+//!     }
+//! });
+//!
+//! let client = app.test_client();
+//!
+//! let response = client.post("/user").json(&serde_json::json!({"name": "sincere", "age": 20})).send();
+//! assert_eq!(response.get_body(), b"sincere");
+//!
+//! let response = client
+//!     .post("/user")
+//!     .json(&serde_json::json!({"name": "s", "email": "nope", "age": 20}))
+//!     .send();
+//!
+//! assert_eq!(response.get_status_code(), 422);
+//! assert_eq!(
+//!     response.get_body(),
+//!     &br#"{"location":"body","errors":[{"field":"name","code":"length","message":"length must be between 2 and 20"},{"field":"email","code":"email","message":"must be a valid email address"}]}"#[..]
+//! );
+//! ```
+use std::collections::{BTreeMap, HashMap};
+use std::error;
+use std::fmt;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::http::{BindError, Location};
+
+/// A type whose values can be checked.
+pub trait Validate {
+    /// Declare the rules of the value.
+    fn rules(&self, validator: &mut Validator);
+
+    /// Check the value against its rules.
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut validator = Validator::new();
+
+        self.rules(&mut validator);
+
+        validator.finish()
+    }
+}
+
+/// A failing rule of a field.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldError {
+    /// Name of the field, nested fields are joined with dots.
+    pub field: String,
+    /// Name of the rule, like `required` or `length`.
+    pub code: String,
+    /// Human readable message.
+    pub message: String,
+}
+
+/// The failing fields of a value, sent back as a 422 Unprocessable Entity.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ValidationErrors {
+    /// Where the value was bound from, if it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+    /// One error for each failing field, in the order of the rules.
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    /// The error of a field.
+    pub fn field(&self, name: &str) -> Option<&FieldError> {
+        self.errors.iter().find(|error| error.field == name)
+    }
+
+    /// Always 422 Unprocessable Entity.
+    #[inline]
+    pub fn status_code(&self) -> u16 {
+        422
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Invalid fields:")?;
+
+        for error in &self.errors {
+            write!(fmt, " {} {},", error.field, error.message)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl error::Error for ValidationErrors {}
+
+/// Error of the `Request::valid_*` binders.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Rejection {
+    /// The value could not be deserialized.
+    Bind(BindError),
+    /// The value was deserialized but is invalid.
+    Invalid(ValidationErrors),
+}
+
+impl Rejection {
+    /// 400 Bad Request or 422 Unprocessable Entity.
+    pub fn status_code(&self) -> u16 {
+        match *self {
+            Rejection::Bind(ref err) => err.status_code(),
+            Rejection::Invalid(ref err) => err.status_code(),
+        }
+    }
+}
+
+impl From<BindError> for Rejection {
+    fn from(err: BindError) -> Rejection {
+        Rejection::Bind(err)
+    }
+}
+
+impl From<ValidationErrors> for Rejection {
+    fn from(err: ValidationErrors) -> Rejection {
+        Rejection::Invalid(err)
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rejection::Bind(ref err) => err.fmt(fmt),
+            Rejection::Invalid(ref err) => err.fmt(fmt),
+        }
+    }
+}
+
+impl error::Error for Rejection {}
+
+/// Collects the failing fields of a value.
+///
+/// Rules are checked in order, and the first failing rule of a field is
+/// the one reported. Absent optional fields pass every rule but
+/// `required`.
+#[derive(Debug, Default)]
+pub struct Validator {
+    prefix: String,
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Validator {
+        Validator::default()
+    }
+
+    /// Rules for a field.
+    pub fn field<'a, T: Value + ?Sized>(&'a mut self, name: &str, value: &'a T) -> Field<'a, T> {
+        Field::new(self, name, Some(value))
+    }
+
+    /// Rules for a field that may be absent.
+    pub fn optional<'a, T: Value>(&'a mut self, name: &str, value: &'a Option<T>) -> Field<'a, T> {
+        Field::new(self, name, value.as_ref())
+    }
+
+    /// Check a nested value, its fields are reported as `name.field`.
+    pub fn nested<V: Validate + ?Sized>(&mut self, name: &str, value: &V) -> &mut Validator {
+        let prefix = self.prefix.clone();
+        self.prefix = self.path(name) + ".";
+
+        value.rules(self);
+
+        self.prefix = prefix;
+        self
+    }
+
+    /// Report an error, for rules spanning several fields.
+    pub fn error(&mut self, field: &str, code: &str, message: &str) -> &mut Validator {
+        self.errors.push(FieldError {
+            field: self.path(field),
+            code: code.to_owned(),
+            message: message.to_owned(),
+        });
+        self
+    }
+
+    /// The errors, if any.
+    pub fn finish(self) -> Result<(), ValidationErrors> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+
+        Err(ValidationErrors {
+            location: None,
+            errors: self.errors,
+        })
+    }
+
+    fn path(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+}
+
+/// Value of a field.
+///
+/// Other types can be validated with `custom` rules by implementing it
+/// with the default method.
+pub trait Value {
+    /// Whether the value fails `required`, an empty or blank value.
+    fn is_blank(&self) -> bool {
+        false
+    }
+}
+
+/// Value with a length, in characters for text.
+pub trait Length: Value {
+    fn length(&self) -> usize;
+}
+
+impl Value for str {
+    fn is_blank(&self) -> bool {
+        self.trim().is_empty()
+    }
+}
+
+impl Length for str {
+    fn length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl Value for String {
+    fn is_blank(&self) -> bool {
+        self.as_str().is_blank()
+    }
+}
+
+impl Length for String {
+    fn length(&self) -> usize {
+        self.as_str().length()
+    }
+}
+
+impl<T> Value for [T] {
+    fn is_blank(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<T> Length for [T] {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> Value for Vec<T> {
+    fn is_blank(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<T> Length for Vec<T> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<K, V, S> Value for HashMap<K, V, S> {
+    fn is_blank(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K, V> Value for BTreeMap<K, V> {
+    fn is_blank(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+macro_rules! impl_value {
+    ($($ty:ty)*) => {
+        $(
+            impl Value for $ty {}
+        )*
+    }
+}
+
+impl_value! { bool char i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize f32 f64 }
+
+/// The rules of a field, see `Validator::field`.
+pub struct Field<'a, T: ?Sized> {
+    validator: &'a mut Validator,
+    name: String,
+    value: Option<&'a T>,
+    failed: bool,
+}
+
+impl<'a, T: Value + ?Sized> Field<'a, T> {
+    fn new(validator: &'a mut Validator, name: &str, value: Option<&'a T>) -> Field<'a, T> {
+        Field {
+            validator,
+            name: name.to_owned(),
+            value,
+            failed: false,
+        }
+    }
+
+    /// Check a present value, unless a rule already failed.
+    fn check<F>(&mut self, code: &str, message: &str, check: F) -> &mut Self
+    where
+        F: FnOnce(&T) -> bool,
+    {
+        if self.failed {
+            return self;
+        }
+
+        if let Some(value) = self.value {
+            if !check(value) {
+                self.failed = true;
+                self.validator.error(&self.name, code, message);
+            }
+        }
+
+        self
+    }
+
+    /// The value is present and not blank.
+    pub fn required(&mut self) -> &mut Self {
+        if !self.failed && self.value.is_none_or(|value| value.is_blank()) {
+            self.failed = true;
+            self.validator.error(&self.name, "required", "is required");
+        }
+
+        self
+    }
+
+    /// The value passes `check`, reported with `code` and `message`.
+    pub fn custom<F>(&mut self, code: &str, message: &str, check: F) -> &mut Self
+    where
+        F: FnOnce(&T) -> bool,
+    {
+        self.check(code, message, check)
+    }
+}
+
+impl<'a, T: Length + ?Sized> Field<'a, T> {
+    /// The length is between `min` and `max`, inclusive.
+    pub fn length(&mut self, min: usize, max: usize) -> &mut Self {
+        let message = format!("length must be between {} and {}", min, max);
+
+        self.check("length", &message, |value| (min..=max).contains(&value.length()))
+    }
+
+    pub fn min_length(&mut self, min: usize) -> &mut Self {
+        let message = format!("length must be at least {}", min);
+
+        self.check("length", &message, |value| value.length() >= min)
+    }
+
+    pub fn max_length(&mut self, max: usize) -> &mut Self {
+        let message = format!("length must be at most {}", max);
+
+        self.check("length", &message, |value| value.length() <= max)
+    }
+}
+
+impl<'a, T: Value + PartialOrd + fmt::Display> Field<'a, T> {
+    /// The value is between `min` and `max`, inclusive.
+    pub fn range(&mut self, min: T, max: T) -> &mut Self {
+        let message = format!("must be between {} and {}", min, max);
+
+        self.check("range", &message, |value| *value >= min && *value <= max)
+    }
+}
+
+impl<'a, T: Value + AsRef<str> + ?Sized> Field<'a, T> {
+    /// The value matches `regex`. Anchor the pattern, as in `^[0-9]{5}$`,
+    /// for the whole value to match, and build it once outside of `rules`,
+    /// which runs on every validation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::OnceLock;
+    ///
+    /// use regex::Regex;
+    ///
+    /// use sincere::validate::{Validate, Validator};
+    ///
+    /// struct Address {
+    ///     zip: String,
+    /// }
+    ///
+    /// impl Validate for Address {
+    ///     fn rules(&self, validator: &mut Validator) {
+    ///         static ZIP: OnceLock<Regex> = OnceLock::new();
+    ///
+    ///         let zip = ZIP.get_or_init(|| Regex::new("^[0-9]{5}$").unwrap());
+    ///
+    ///         validator.field("zip", &self.zip).regex(zip);
+    ///     }
+    /// }
+    ///
+    /// assert!(Address { zip: "12345".to_owned() }.validate().is_ok());
+    /// assert!(Address { zip: "123456".to_owned() }.validate().is_err());
+    /// ```
+    pub fn regex(&mut self, regex: &Regex) -> &mut Self {
+        self.check("regex", "has an invalid format", |value| {
+            regex.is_match(value.as_ref())
+        })
+    }
+
+    /// The value looks like an email address, `local@domain.tld`.
+    pub fn email(&mut self) -> &mut Self {
+        self.check("email", "must be a valid email address", |value| {
+            is_email(value.as_ref())
+        })
+    }
+}
+
+fn is_email(value: &str) -> bool {
+    let (local, domain) = match value.rsplit_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+
+    let local_valid = !local.is_empty()
+        && local.len() <= 64
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && local
+            .chars()
+            .all(|c| !c.is_whitespace() && !c.is_control() && !"@\"(),:;<>[\\]".contains(c));
+
+    let labels: Vec<&str> = domain.split('.').collect();
+
+    let domain_valid = domain.len() <= 255
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        });
+
+    local_valid && domain_valid
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::{is_email, Validate, Validator};
+
+    struct Address {
+        city: String,
+        zip: String,
+    }
+
+    impl Validate for Address {
+        fn rules(&self, validator: &mut Validator) {
+            validator.field("city", &self.city).required();
+            validator
+                .field("zip", &self.zip)
+                .regex(&Regex::new("^[0-9]{5}$").unwrap());
+        }
+    }
+
+    struct Signup {
+        name: String,
+        password: String,
+        confirm: String,
+        nickname: Option<String>,
+        age: Option<u32>,
+        tags: Vec<String>,
+        address: Address,
+    }
+
+    impl Validate for Signup {
+        fn rules(&self, validator: &mut Validator) {
+            validator.field("name", &self.name).required().max_length(5);
+            validator.field("password", &self.password).min_length(8);
+            validator.optional("nickname", &self.nickname).length(2, 4);
+            validator.optional("age", &self.age).required().range(18, 99);
+            validator.field("tags", &self.tags).custom("unique", "must be unique", |tags| {
+                tags.iter().enumerate().all(|(i, tag)| !tags[..i].contains(tag))
+            });
+            validator.nested("address", &self.address);
+
+            if self.password != self.confirm {
+                validator.error("confirm", "match", "must match password");
+            }
+        }
+    }
+
+    fn signup() -> Signup {
+        Signup {
+            name: "danc".to_owned(),
+            password: "12345678".to_owned(),
+            confirm: "12345678".to_owned(),
+            nickname: None,
+            age: Some(20),
+            tags: vec!["a".to_owned(), "b".to_owned()],
+            address: Address {
+                city: "Paris".to_owned(),
+                zip: "75001".to_owned(),
+            },
+        }
+    }
+
+    #[test]
+    fn rules() {
+        assert!(signup().validate().is_ok());
+
+        let mut value = signup();
+        value.name = "   ".to_owned();
+        value.password = "1234".to_owned();
+        value.nickname = Some("日本語の".to_owned());
+        value.age = None;
+        value.tags.push("a".to_owned());
+        value.address.zip = "750012".to_owned();
+
+        let errors = value.validate().unwrap_err();
+
+        let codes: Vec<(&str, &str)> = errors
+            .errors
+            .iter()
+            .map(|error| (error.field.as_str(), error.code.as_str()))
+            .collect();
+
+        assert_eq!(
+            codes,
+            vec![
+                ("name", "required"),
+                ("password", "length"),
+                ("age", "required"),
+                ("tags", "unique"),
+                ("address.zip", "regex"),
+                ("confirm", "match"),
+            ]
+        );
+
+        assert_eq!(errors.field("password").unwrap().message, "length must be at least 8");
+
+        let mut value = signup();
+        value.name = "sincere".to_owned();
+        value.age = Some(16);
+
+        let errors = value.validate().unwrap_err();
+
+        assert_eq!(errors.field("name").unwrap().message, "length must be at most 5");
+        assert_eq!(errors.field("age").unwrap().message, "must be between 18 and 99");
+    }
+
+    #[test]
+    fn whole_match() {
+        struct Code(&'static str, &'static str);
+
+        impl Validate for Code {
+            fn rules(&self, validator: &mut Validator) {
+                validator.field("code", self.0).regex(&Regex::new("^(?:a|ab)$").unwrap());
+                validator.field("digits", self.1).regex(&Regex::new(r"^\d+?$").unwrap());
+            }
+        }
+
+        assert!(Code("ab", "123").validate().is_ok());
+        assert!(Code("a", "1").validate().is_ok());
+
+        let errors = Code("abc", "12a").validate().unwrap_err();
+        assert_eq!(errors.field("code").unwrap().code, "regex");
+        assert_eq!(errors.field("digits").unwrap().code, "regex");
+    }
+
+    #[test]
+    fn emails() {
+        for email in &["a@b.co", "first.last+tag@sub.example.org", "用户@例子.中国"] {
+            assert!(is_email(email), "{}", email);
+        }
+
+        for email in &["", "a", "@b.co", "a@", "a@b", "a b@c.de", "a..b@c.de", ".a@b.co", "a@-b.co", "a@b..co"] {
+            assert!(!is_email(email), "{}", email);
+        }
+    }
+}